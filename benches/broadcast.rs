@@ -0,0 +1,55 @@
+//! Compares `broadcast_packet` and `broadcast_packet_parallel` on nodes of growing degree.
+//!
+//! Run with `cargo bench --features parallel,test-util --bench broadcast`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use crossbeam_channel::{unbounded, Receiver};
+use network_node::{fragment_message, MockNode, NetworkNode};
+use wg_2024::packet::{NodeType, Packet};
+
+/// Creates a node with `count` neighbours, each with its own channel.
+fn node_with_neighbours(count: u8) -> (MockNode, Vec<Receiver<Packet>>) {
+    let mut node = MockNode::new(0, NodeType::Drone);
+    let receivers = (1..=count)
+        .map(|neighbour| {
+            let (sender, receiver) = unbounded();
+            node.add_channel(neighbour, sender);
+            receiver
+        })
+        .collect();
+    (node, receivers)
+}
+
+/// Empties the channels filled by a broadcast, so that they do not grow across iterations.
+fn drain(node: &MockNode, receivers: &[Receiver<Packet>]) {
+    for receiver in receivers {
+        receiver.try_iter().for_each(drop);
+    }
+    node.take_sim_events();
+}
+
+fn bench_broadcast(c: &mut Criterion) {
+    let packet = fragment_message(&[0; 128], 1).remove(0);
+    let mut group = c.benchmark_group("broadcast");
+    for neighbours in [5, 10, 20, 50] {
+        let (mut node, receivers) = node_with_neighbours(neighbours);
+        group.bench_with_input(BenchmarkId::new("sequential", neighbours), &packet, |b, packet| {
+            b.iter(|| {
+                node.broadcast_packet(packet.clone(), 0)
+                    .expect("Failed to broadcast packet");
+                drain(&node, &receivers);
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", neighbours), &packet, |b, packet| {
+            b.iter(|| {
+                node.broadcast_packet_parallel(packet.clone(), 0)
+                    .expect("Failed to broadcast packet");
+                drain(&node, &receivers);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_broadcast);
+criterion_main!(benches);
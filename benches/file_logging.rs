@@ -0,0 +1,62 @@
+//! Times writing 100k lines to the log file, buffered as `write_to_log` does, against writing
+//! each line to the file with its own `write_all`, as it did before the `BufWriter`.
+//!
+//! Run with `cargo bench --bench file_logging`. Both benchmarks write to files of their own
+//! in the temporary directory, which are removed afterwards.
+
+use chrono::Local;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use network_node::logging::write_to_log;
+use network_node::{disable_logging, enable_logging, flush_logs, redirect_node_logs_to_file, LogLevel};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+/// Number of lines written per iteration.
+const LINES: u64 = 100_000;
+
+fn bench_file_logging(c: &mut Criterion) {
+    let mut group = c.benchmark_group("file_logging");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(LINES));
+
+    // Only the node's own file is written: nothing goes to stdout or to "network.log"
+    let buffered_path = std::env::temp_dir().join("dr_ones_buffered_bench.log");
+    disable_logging();
+    redirect_node_logs_to_file(1, &buffered_path);
+    group.bench_function("buffered", |b| {
+        b.iter(|| {
+            for line in 0..LINES {
+                write_to_log(1, format!("Forwarded packet {}", line), LogLevel::Info);
+            }
+            flush_logs();
+        })
+    });
+
+    let unbuffered_path = std::env::temp_dir().join("dr_ones_unbuffered_bench.log");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&unbuffered_path)
+        .expect("Failed to open the unbuffered log file");
+    group.bench_function("unbuffered", |b| {
+        b.iter(|| {
+            for line in 0..LINES {
+                let log_line = format!(
+                    "[{}] [INFO ] [NODE 1] Forwarded packet {}\n",
+                    Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                    line
+                );
+                file.write_all(log_line.as_bytes())
+                    .expect("Failed to write the unbuffered log file");
+            }
+        })
+    });
+    group.finish();
+
+    enable_logging();
+    let _ = fs::remove_file(buffered_path);
+    let _ = fs::remove_file(unbuffered_path);
+}
+
+criterion_group!(benches, bench_file_logging);
+criterion_main!(benches);
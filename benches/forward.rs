@@ -0,0 +1,44 @@
+//! Times `forward_packet` on nodes of growing degree.
+//!
+//! The time per packet must not grow with the number of neighbours: `forward_packet` only
+//! looks up the sender of the next hop, it does not clone the map of the senders.
+//! Run with `cargo bench --features test-util --bench forward`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use crossbeam_channel::unbounded;
+use network_node::{build_routing_header, MockNode, NetworkNode};
+use wg_2024::packet::{Ack, NodeType, Packet, PacketType};
+
+/// Number of packets forwarded per iteration.
+const PACKETS: u64 = 10_000;
+
+fn bench_forward(c: &mut Criterion) {
+    let mut group = c.benchmark_group("forward");
+    group.throughput(Throughput::Elements(PACKETS));
+    for neighbours in [1, 10, 50, 200] {
+        let mut node = MockNode::new(0, NodeType::Drone);
+        let (sender, receiver) = unbounded();
+        node.add_channel(1, sender);
+        for neighbour in 2..=neighbours {
+            node.add_channel(neighbour, unbounded().0);
+        }
+        group.bench_function(BenchmarkId::from_parameter(neighbours), |b| {
+            b.iter(|| {
+                for session_id in 0..PACKETS {
+                    let packet = Packet {
+                        pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+                        routing_header: build_routing_header(vec![0, 1]),
+                        session_id,
+                    };
+                    node.forward_packet(packet).expect("Failed to forward packet");
+                }
+                receiver.try_iter().for_each(drop);
+                node.take_sim_events();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_forward);
+criterion_main!(benches);
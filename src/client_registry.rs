@@ -0,0 +1,103 @@
+//! Registry of the clients registered to a communication server.
+
+use std::collections::BTreeSet;
+use wg_2024::network::NodeId;
+
+use crate::network_node::SerializableMessage;
+
+/// The clients registered to a communication server with `RegisterToCommunicationServer`.
+///
+/// Registering an already registered client, or unregistering an unknown one, has no effect,
+/// so every server implementation answers repeated requests the same way.
+#[derive(Clone, Debug, Default)]
+pub struct ClientRegistry {
+    clients: BTreeSet<NodeId>,
+}
+
+impl ClientRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a client.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the client was not already registered.
+    pub fn register(&mut self, client: NodeId) -> bool {
+        self.clients.insert(client)
+    }
+
+    /// Unregisters a client.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the client was registered.
+    pub fn unregister(&mut self, client: NodeId) -> bool {
+        self.clients.remove(&client)
+    }
+
+    /// Returns whether a client is registered.
+    pub fn is_registered(&self, client: NodeId) -> bool {
+        self.clients.contains(&client)
+    }
+
+    /// Returns the registered clients, sorted by id.
+    pub fn list(&self) -> Vec<NodeId> {
+        self.clients.iter().copied().collect()
+    }
+
+    /// Returns the number of registered clients.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Returns whether no client is registered.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// Builds the server's answer to a `ClientListRequest`, listing the clients sorted by id.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_id` - The id of the answering server.
+    pub fn client_list_response(&self, server_id: NodeId) -> SerializableMessage {
+        SerializableMessage::ClientListResponse(server_id, self.list())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_registration() {
+        let mut registry = ClientRegistry::new();
+        assert!(registry.register(5));
+        assert!(!registry.register(5));
+        assert_eq!(registry.len(), 1);
+        assert!(registry.is_registered(5));
+
+        assert!(registry.unregister(5));
+        assert!(!registry.unregister(5));
+        assert!(!registry.is_registered(5));
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_list_is_sorted() {
+        let mut registry = ClientRegistry::new();
+        for client in [9, 2, 7, 4] {
+            registry.register(client);
+        }
+        registry.unregister(7);
+
+        assert_eq!(registry.list(), vec![2, 4, 9]);
+        assert_eq!(
+            registry.client_list_response(1),
+            SerializableMessage::ClientListResponse(1, vec![2, 4, 9])
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Error type shared by the helpers of this crate.
+
+use std::fmt;
+use wg_2024::network::NodeId;
+
+use crate::network_node::DeserializeError;
+
+/// Errors returned by the networking and serialization helpers of this crate.
+#[derive(Debug)]
+pub enum CommonError {
+    /// The node has no channel towards the next hop (argument is the next hop id).
+    NextHopUnreachable(NodeId),
+    /// The channel towards the next hop is closed, e.g. because the neighbour crashed
+    /// (argument is the next hop id).
+    ChannelClosed(NodeId),
+    /// The node is not the current hop of the packet's routing header.
+    NotCurrentHop,
+    /// The route of the packet contains no hop.
+    EmptyRoute,
+    /// The route of the packet visits a node more than once, so the packet would loop
+    /// (argument is the repeated node id).
+    InvalidRoute(NodeId),
+    /// The hop index of the packet is past the end of its route, e.g. a malformed or
+    /// already delivered packet.
+    RouteExhausted,
+    /// The operation requires a `MsgFragment` packet.
+    NonFragmentPacket,
+    /// A message could not be rebuilt from its fragments.
+    DeserializeFailed(DeserializeError),
+    /// The serialized message is larger than the allowed maximum and was not fragmented.
+    MessageTooLarge { size: usize, max: usize },
+    /// The node already has a channel towards this neighbour (argument is the neighbour id).
+    AlreadyNeighbor(NodeId),
+    /// The channel towards the simulation controller is closed, e.g. because the controller
+    /// has shut down.
+    ControllerUnreachable,
+    /// The node has already forwarded the maximum number of packets of this session, which is
+    /// likely caught in a routing loop (argument is the session id).
+    ForwardLimitExceeded(u64),
+    /// A builder was asked to build a message without a required field (argument is the
+    /// field name).
+    MissingField(&'static str),
+}
+
+impl fmt::Display for CommonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommonError::NextHopUnreachable(node_id) => {
+                write!(f, "no channel towards next hop {}", node_id)
+            }
+            CommonError::ChannelClosed(node_id) => {
+                write!(f, "the channel towards node {} is closed", node_id)
+            }
+            CommonError::NotCurrentHop => write!(f, "this node is not the current hop"),
+            CommonError::EmptyRoute => write!(f, "the route is empty"),
+            CommonError::InvalidRoute(node_id) => write!(f, "node {} appears twice in the route", node_id),
+            CommonError::RouteExhausted => write!(f, "the hop index is past the end of the route"),
+            CommonError::NonFragmentPacket => write!(f, "the packet is not a message fragment"),
+            CommonError::DeserializeFailed(e) => write!(f, "deserialization failed: {}", e),
+            CommonError::MessageTooLarge { size, max } => {
+                write!(f, "the message is {} bytes long, more than the maximum of {}", size, max)
+            }
+            CommonError::AlreadyNeighbor(node_id) => {
+                write!(f, "node {} is already a neighbour", node_id)
+            }
+            CommonError::ControllerUnreachable => {
+                write!(f, "the simulation controller is unreachable")
+            }
+            CommonError::ForwardLimitExceeded(session_id) => {
+                write!(f, "too many packets of session {} were forwarded", session_id)
+            }
+            CommonError::MissingField(field) => write!(f, "the {} field is not set", field),
+        }
+    }
+}
+
+impl std::error::Error for CommonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CommonError::DeserializeFailed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DeserializeError> for CommonError {
+    fn from(error: DeserializeError) -> Self {
+        CommonError::DeserializeFailed(error)
+    }
+}
@@ -0,0 +1,203 @@
+//! Storage of the files served by content servers.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wg_2024::network::NodeId;
+
+use crate::network_node::{AppError, SerializableMessage};
+
+/// The files a content server answers `FileListRequest` and `FileRequest` with.
+///
+/// Implemented by `InMemoryFileStore`, handy for tests, and by `DiskFileStore`, which serves
+/// the files of a directory.
+pub trait FileStore {
+    /// Returns the names of the stored files, sorted.
+    fn list(&self) -> Vec<String>;
+
+    /// Returns the content of a file, or `None` if there is no file with that name.
+    fn get(&self, name: &str) -> Option<Vec<u8>>;
+
+    /// Returns the content of a file.
+    ///
+    /// # Returns
+    ///
+    /// The content, or `AppError::FileNotFound` if there is no file with that name.
+    fn fetch(&self, name: &str) -> Result<Vec<u8>, AppError> {
+        self.get(name).ok_or(AppError::FileNotFound)
+    }
+
+    /// Builds the server's answer to a `FileListRequest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_id` - The id of the answering server.
+    fn file_list_response(&self, server_id: NodeId) -> SerializableMessage {
+        SerializableMessage::FileListResponse(server_id, self.list())
+    }
+
+    /// Builds the server's answer to a `FileRequest`.
+    ///
+    /// `FileFound` carries the file as text: invalid UTF-8 sequences are replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_id` - The id of the answering server.
+    /// * `name` - The name of the requested file.
+    ///
+    /// # Returns
+    ///
+    /// A `FileFound`, or an `ErrorMessage` with `AppError::FileNotFound` if there is no file
+    /// with that name.
+    fn file_response(&self, server_id: NodeId, name: &str) -> SerializableMessage {
+        match self.fetch(name) {
+            Ok(content) => SerializableMessage::FileFound(
+                server_id,
+                name.to_string(),
+                String::from_utf8_lossy(&content).into_owned(),
+            ),
+            Err(e) => SerializableMessage::ErrorMessage(server_id, e),
+        }
+    }
+}
+
+/// A `FileStore` keeping its files in memory.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryFileStore {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl InMemoryFileStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, replacing any file with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the file.
+    /// * `content` - The content of the file.
+    pub fn insert(&mut self, name: impl Into<String>, content: impl Into<Vec<u8>>) {
+        self.files.insert(name.into(), content.into());
+    }
+
+    /// Removes a file.
+    ///
+    /// # Returns
+    ///
+    /// The content of the removed file, or `None` if there was no file with that name.
+    pub fn remove(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.files.remove(name)
+    }
+}
+
+impl FileStore for InMemoryFileStore {
+    fn list(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.files.keys().cloned().collect();
+        names.sort_unstable();
+        names
+    }
+
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.files.get(name).cloned()
+    }
+}
+
+/// A `FileStore` serving the regular files of a directory, read when requested.
+///
+/// Subdirectories are not served, and names that are not plain file names (e.g. `../secret`)
+/// are never found, so clients cannot read outside the directory.
+#[derive(Clone, Debug)]
+pub struct DiskFileStore {
+    dir: PathBuf,
+}
+
+impl DiskFileStore {
+    /// Creates a store serving the files of a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the files.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl FileStore for DiskFileStore {
+    /// Returns the names of the regular files of the directory, sorted. An unreadable
+    /// directory has no files.
+    fn list(&self) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        if Path::new(name).file_name().and_then(|file_name| file_name.to_str()) != Some(name) {
+            return None;
+        }
+        let path = self.dir.join(name);
+        if !path.is_file() {
+            return None;
+        }
+        fs::read(path).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_store() -> InMemoryFileStore {
+        let mut store = InMemoryFileStore::new();
+        store.insert("b.txt", "second");
+        store.insert("a.txt", "first");
+        store
+    }
+
+    #[test]
+    fn test_file_hit() {
+        let store = test_store();
+        assert_eq!(store.list(), vec!["a.txt".to_string(), "b.txt".to_string()]);
+        assert_eq!(store.fetch("a.txt"), Ok(b"first".to_vec()));
+        assert_eq!(
+            store.file_response(1, "b.txt"),
+            SerializableMessage::FileFound(1, "b.txt".to_string(), "second".to_string())
+        );
+    }
+
+    #[test]
+    fn test_file_miss() {
+        let store = test_store();
+        assert_eq!(store.fetch("c.txt"), Err(AppError::FileNotFound));
+        assert_eq!(
+            store.file_response(1, "c.txt"),
+            SerializableMessage::ErrorMessage(1, AppError::FileNotFound)
+        );
+    }
+
+    #[test]
+    fn test_disk_file_store() {
+        let dir = std::env::temp_dir().join("dr_ones_file_store_98");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).expect("Failed to create the test directory");
+        fs::write(dir.join("notes.txt"), "notes").expect("Failed to write the test file");
+
+        let store = DiskFileStore::new(&dir);
+        assert_eq!(store.list(), vec!["notes.txt".to_string()]);
+        assert_eq!(store.get("notes.txt"), Some(b"notes".to_vec()));
+        assert_eq!(store.fetch("nested"), Err(AppError::FileNotFound));
+        assert_eq!(store.get("../dr_ones_file_store_98/notes.txt"), None);
+
+        fs::remove_dir_all(&dir).expect("Failed to remove the test directory");
+    }
+}
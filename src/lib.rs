@@ -3,8 +3,33 @@
 //! This crate provides shared functionality used by the drone, client,
 //! and server components of the network simulator.
 
+mod client_registry;
+mod error;
+mod file_store;
 pub mod logging;
+#[cfg(feature = "test-util")]
+mod mock_node;
 mod network_node;
+mod node_core;
+mod packet_recorder;
+mod seen_flood_cache;
+mod topology;
 
-pub use logging::{disable_logging, enable_logging, is_logging_enabled, redirect_logs_to_file};
+pub use client_registry::ClientRegistry;
+pub use error::CommonError;
+pub use file_store::{DiskFileStore, FileStore, InMemoryFileStore};
+pub use logging::{
+    clear_custom_log_sink, clear_node_log_level, current_log_level, current_log_sinks,
+    disable_log_dedup, disable_logging, enable_logging, error_count, flush_logs, is_logging_enabled,
+    redirect_logs_to_channel, redirect_logs_to_file, redirect_node_logs_to_file, reset_error_counts,
+    set_custom_log_sink, set_log_colors, set_log_dedup_window, set_log_level, set_log_sinks,
+    set_log_timezone, set_node_log_level, ConsoleLogWriter, FileLogWriter, LogLevel, LogRecord,
+    LogSink, LogTimezone, LogWriter,
+};
+#[cfg(feature = "test-util")]
+pub use mock_node::MockNode;
 pub use network_node::*;
+pub use node_core::*;
+pub use packet_recorder::*;
+pub use seen_flood_cache::*;
+pub use topology::*;
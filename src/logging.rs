@@ -1,42 +1,458 @@
 use once_cell::sync::Lazy;
-use std::sync::Mutex;
-use std::fs::{File, OpenOptions};
-use std::io::Write;
-use chrono::Local;
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::ops::BitOr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local, Utc};
+use crossbeam_channel::Sender;
+use wg_2024::network::NodeId;
 
-static LOGGING_ENABLED: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(true));
-static LOG_FILE: Lazy<Mutex<Option<File>>> = Lazy::new(|| Mutex::new(None));
+static LOG_SINKS: Lazy<Mutex<LogSink>> = Lazy::new(|| Mutex::new(LogSink::STDOUT));
+static LOG_FILE: Lazy<Mutex<Option<BufWriter<File>>>> = Lazy::new(|| Mutex::new(None));
+static NODE_LOG_FILES: Lazy<Mutex<HashMap<NodeId, BufWriter<File>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static LAST_LOG_FLUSH: Lazy<Mutex<Instant>> = Lazy::new(|| Mutex::new(Instant::now()));
+static LOG_LEVEL: Lazy<Mutex<LogLevel>> = Lazy::new(|| Mutex::new(LogLevel::Info));
+static ERROR_COUNTS: Lazy<Vec<AtomicU64>> = Lazy::new(|| (0..=NodeId::MAX).map(|_| AtomicU64::new(0)).collect());
+static LOG_DEDUP: Lazy<Mutex<LogDedup>> = Lazy::new(|| Mutex::new(LogDedup {
+    window: None,
+    recent: HashMap::new(),
+}));
+static LOG_COLORS: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
+static NODE_LOG_LEVELS: Lazy<Mutex<HashMap<NodeId, LogLevel>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static LOG_NODE_FILTER: Lazy<Mutex<Option<HashSet<NodeId>>>> = Lazy::new(|| Mutex::new(None));
+static LOG_FORMAT: Lazy<Mutex<LogFormat>> = Lazy::new(|| Mutex::new(LogFormat::Text));
+static LOG_TIMEZONE: Lazy<Mutex<LogTimezone>> = Lazy::new(|| Mutex::new(LogTimezone::Local));
+static LOG_CHANNEL: Lazy<Mutex<Option<Sender<LogRecord>>>> = Lazy::new(|| Mutex::new(None));
+static CUSTOM_LOG_SINK: Lazy<Mutex<Option<Arc<Mutex<Box<dyn LogWriter>>>>>> = Lazy::new(|| Mutex::new(None));
+static CAPTURED_LOGS: Lazy<Mutex<Option<Vec<String>>>> = Lazy::new(|| Mutex::new(None));
+static LOG_ROTATION: Lazy<Mutex<LogRotation>> = Lazy::new(|| Mutex::new(LogRotation {
+    max_bytes: None,
+    keep: DEFAULT_ROTATION_KEEP,
+}));
+
+thread_local! {
+    /// Whether this thread is inside the custom sink's `LogWriter::write`.
+    static WRITING_CUSTOM_LOG: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Path of the shared log file.
+const LOG_FILE_PATH: &str = "network.log";
+
+/// Number of rotated log files kept by default (`network.log.1` to `network.log.5`).
+const DEFAULT_ROTATION_KEEP: usize = 5;
+
+/// Maximum time log lines stay in the write buffers before the log files are flushed.
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Set of destinations a log message is written to.
+///
+/// Sinks can be combined with `|`, e.g. `LogSink::STDOUT | LogSink::FILE`
+/// to log both to the console and to the log file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogSink(u8);
+
+impl LogSink {
+    /// No destination: messages are discarded.
+    pub const NONE: LogSink = LogSink(0);
+    /// Messages are printed to stdout (or stderr for warnings and errors),
+    /// or handed to the `log` crate facade with the `log-facade` feature.
+    pub const STDOUT: LogSink = LogSink(1);
+    /// Messages are written to the configured log files.
+    pub const FILE: LogSink = LogSink(1 << 1);
+
+    /// Returns whether every sink of `other` is enabled in `self`.
+    pub fn contains(self, other: LogSink) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `self` without the sinks of `other`.
+    pub fn without(self, other: LogSink) -> LogSink {
+        LogSink(self.0 & !other.0)
+    }
+}
+
+impl BitOr for LogSink {
+    type Output = LogSink;
+
+    fn bitor(self, other: LogSink) -> LogSink {
+        LogSink(self.0 | other.0)
+    }
+}
+
+/// A log message as delivered to a channel configured with `redirect_logs_to_channel`, or to a
+/// `LogWriter`.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub node_id: NodeId,
+    pub level: LogLevel,
+    pub category: Option<&'static str>,
+    pub timestamp: DateTime<Local>,
+    pub message: String,
+}
+
+/// A destination of log records, e.g. syslog or a database.
+///
+/// Named `LogWriter` since `LogSink` is the set of built-in destinations. The console and the
+/// log file are written through `ConsoleLogWriter` and `FileLogWriter`; other destinations are
+/// plugged in with `set_custom_log_sink`.
+pub trait LogWriter: Send {
+    /// Writes a log record to the destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record of a message logged through the logging macros.
+    fn write(&mut self, record: &LogRecord);
+}
+
+/// Prints log records to the console, as `LogSink::STDOUT` does.
+pub struct ConsoleLogWriter;
+
+impl LogWriter for ConsoleLogWriter {
+    fn write(&mut self, record: &LogRecord) {
+        let message = with_category_tag(record.category, &record.message);
+        write_to_console(record.node_id, record.level, &message);
+    }
+}
+
+/// Writes log records to the shared or per-node log files, as `LogSink::FILE` does.
+pub struct FileLogWriter;
+
+impl LogWriter for FileLogWriter {
+    fn write(&mut self, record: &LogRecord) {
+        write_categorized_to_log(record.node_id, record.category, &record.message, record.level);
+    }
+}
+
+/// Categories of log messages, to filter the logs by subsystem.
+///
+/// A category is passed to the logging macros as a path to a `&'static str` constant, e.g.
+/// `log_status!(id, category::FLOOD, "...")`, and rendered as `[FLOOD]` in the log lines.
+/// Components can declare their own constants the same way.
+pub mod category {
+    /// Flood requests and responses.
+    pub const FLOOD: &str = "FLOOD";
+    /// Forwarding of routed packets.
+    pub const ROUTING: &str = "ROUTING";
+    /// Commands received from the simulation controller.
+    pub const COMMAND: &str = "COMMAND";
+}
+
+/// Output format of the lines written to log files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `[timestamp] [LEVEL] [NODE id] message`, or `[timestamp] [LEVEL] [NODE id] [CATEGORY] message`
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp`, `level`, `node_id`, `category` (only for
+    /// categorized messages) and `message` fields.
+    Json,
+}
+
+/// Time zone of the timestamps written to log files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogTimezone {
+    /// The local time of the machine, e.g. `2024-01-01 13:00:00.000`.
+    #[default]
+    Local,
+    /// UTC, with a `Z` suffix, e.g. `2024-01-01 12:00:00.000Z`, to correlate the logs
+    /// of machines in different time zones.
+    Utc,
+}
+
+/// Size-based rotation settings for the shared log file.
+struct LogRotation {
+    /// Maximum size of the shared log file, `None` if rotation is disabled.
+    max_bytes: Option<u64>,
+    /// Number of rotated files to keep.
+    keep: usize,
+}
+
+/// Deduplication of the identical messages logged by a node within a time window.
+struct LogDedup {
+    /// The deduplication window, `None` if deduplication is disabled.
+    window: Option<Duration>,
+    /// For each `(node_id, message hash)`, when the message was last written and how many
+    /// identical messages were suppressed since.
+    recent: HashMap<(NodeId, u64), (Instant, u64)>,
+}
+
+/// Number of tracked messages above which the entries outside the window are forgotten.
+const LOG_DEDUP_MAX_ENTRIES: usize = 1024;
+
+/// Severity of a log message, ordered from the most verbose to the most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Returns the tag used for this level in log lines (e.g. `"INFO"`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "TRACE",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
 
 /// Enables logging to stdout and resets any log file redirection.
 ///
-/// This function sets the logging status to enabled (stdout)
-/// and clears any previously configured log file.
+/// This function sets the logging status to enabled (stdout) and clears any previously
+/// configured log file, including per-node files, and any log channel.
+/// The log files are flushed before being closed.
 pub fn enable_logging() {
-    *LOGGING_ENABLED.lock().expect("Failed to get LOGGING_ENABLED lock") = true;
+    flush_logs();
+    *LOG_CHANNEL.lock().expect("Failed to get LOG_CHANNEL lock") = None;
+    *LOG_SINKS.lock().expect("Failed to get LOG_SINKS lock") = LogSink::STDOUT;
     let mut file_guard = LOG_FILE.lock().expect("Failed to get LOG_FILE lock");
     *file_guard = None;
+    NODE_LOG_FILES.lock().expect("Failed to get NODE_LOG_FILES lock").clear();
 }
 
 /// Disables logging to stdout.
 ///
-/// This function sets the logging status to disabled.
-/// It does not affect an already configured log file.
+/// This function removes stdout from the log sinks.
+/// It does not affect an already configured log file, which is only flushed.
 pub fn disable_logging() {
-    *LOGGING_ENABLED.lock().expect("Failed to get LOGGING_ENABLED lock") = false;
+    flush_logs();
+    let mut sinks = LOG_SINKS.lock().expect("Failed to get LOG_SINKS lock");
+    *sinks = sinks.without(LogSink::STDOUT);
 }
 
 /// Redirects log output to a file.
 ///
 /// This function disables stdout logging and configures logging to a file
 /// named "network.log". Log messages will be appended to this file.
+///
+/// Lines are buffered, and warnings and errors are the only ones flushed right away:
+/// call `flush_logs` before the process exits, or the last lines may be lost.
 pub fn redirect_logs_to_file() {
-    *LOGGING_ENABLED.lock().expect("Failed to get LOGGING_ENABLED lock") = false;
+    *LOG_SINKS.lock().expect("Failed to get LOG_SINKS lock") = LogSink::FILE;
     let mut file_guard = LOG_FILE.lock().expect("Failed to get LOG_FILE lock");
-    *file_guard = Some(OpenOptions::new()
+    *file_guard = Some(BufWriter::new(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG_FILE_PATH)
+        .expect("Failed to open log file")));
+}
+
+/// Sets the destinations every log message is written to.
+///
+/// If `sinks` contains `LogSink::FILE` and no shared log file is open yet,
+/// "network.log" is opened in append mode.
+///
+/// # Arguments
+///
+/// * `sinks` - The destinations to enable, e.g. `LogSink::STDOUT | LogSink::FILE`.
+pub fn set_log_sinks(sinks: LogSink) {
+    *LOG_SINKS.lock().expect("Failed to get LOG_SINKS lock") = sinks;
+    if sinks.contains(LogSink::FILE) {
+        let mut file_guard = LOG_FILE.lock().expect("Failed to get LOG_FILE lock");
+        if file_guard.is_none() {
+            *file_guard = Some(BufWriter::new(OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(LOG_FILE_PATH)
+                .expect("Failed to open log file")));
+        }
+    }
+}
+
+/// Returns the destinations log messages are currently written to.
+///
+/// Only takes the sinks lock, so it is cheap enough to be called from a node's main loop.
+pub fn current_log_sinks() -> LogSink {
+    *LOG_SINKS.lock().expect("Failed to get LOG_SINKS lock")
+}
+
+/// Enables size-based rotation of the shared log file.
+///
+/// Before each write, if the line would make "network.log" grow beyond `max_bytes`,
+/// the file is renamed to "network.log.1" (shifting any existing "network.log.1" to
+/// "network.log.2", and so on) and a fresh file is opened.
+/// Per-node log files are not rotated.
+///
+/// # Arguments
+///
+/// * `max_bytes` - The maximum size in bytes of the shared log file.
+pub fn set_log_rotation(max_bytes: u64) {
+    LOG_ROTATION.lock().expect("Failed to get LOG_ROTATION lock").max_bytes = Some(max_bytes);
+}
+
+/// Sets how many rotated log files are kept; older ones are deleted.
+///
+/// The default is 5.
+///
+/// # Arguments
+///
+/// * `keep` - The number of rotated files to keep.
+pub fn set_log_rotation_keep(keep: usize) {
+    LOG_ROTATION.lock().expect("Failed to get LOG_ROTATION lock").keep = keep;
+}
+
+/// Disables log rotation; the shared log file then grows without limit.
+pub fn disable_log_rotation() {
+    LOG_ROTATION.lock().expect("Failed to get LOG_ROTATION lock").max_bytes = None;
+}
+
+/// Rotates the shared log file if writing `incoming` more bytes would exceed the limit.
+///
+/// Must be called while holding the `LOG_FILE` lock, `file` being the guarded file.
+/// The lines still buffered count towards the size of the file, and are flushed to it
+/// before it is rotated.
+fn rotate_log_file_if_needed(file: &mut BufWriter<File>, incoming: u64) {
+    let (max_bytes, keep) = {
+        let rotation = LOG_ROTATION.lock().expect("Failed to get LOG_ROTATION lock");
+        match rotation.max_bytes {
+            Some(max_bytes) => (max_bytes, rotation.keep),
+            None => return,
+        }
+    };
+
+    let current_len = match file.get_ref().metadata() {
+        Ok(metadata) => metadata.len() + file.buffer().len() as u64,
+        Err(e) => {
+            eprintln!("Failed to read log file metadata: {}", e);
+            return;
+        }
+    };
+    if current_len == 0 || current_len + incoming <= max_bytes {
+        return;
+    }
+
+    if let Err(e) = file.flush() {
+        eprintln!("Failed to flush log file before rotation: {}", e);
+    }
+
+    // Shift network.log.N-1 -> network.log.N, ..., network.log -> network.log.1
+    if keep == 0 {
+        let _ = fs::remove_file(LOG_FILE_PATH);
+    } else {
+        let _ = fs::remove_file(format!("{}.{}", LOG_FILE_PATH, keep));
+        for index in (1..keep).rev() {
+            let from = format!("{}.{}", LOG_FILE_PATH, index);
+            if Path::new(&from).exists() {
+                if let Err(e) = fs::rename(&from, format!("{}.{}", LOG_FILE_PATH, index + 1)) {
+                    eprintln!("Failed to rotate log file {}: {}", from, e);
+                }
+            }
+        }
+        if let Err(e) = fs::rename(LOG_FILE_PATH, format!("{}.1", LOG_FILE_PATH)) {
+            eprintln!("Failed to rotate log file {}: {}", LOG_FILE_PATH, e);
+            return;
+        }
+    }
+
+    match OpenOptions::new().create(true).append(true).open(LOG_FILE_PATH) {
+        Ok(new_file) => *file = BufWriter::new(new_file),
+        Err(e) => eprintln!("Failed to reopen log file after rotation: {}", e),
+    }
+}
+
+/// Redirects the log output of a single node to its own file.
+///
+/// Messages from `node_id` are appended to the file at `path` instead of the
+/// shared "network.log"; every other node keeps using the shared file.
+/// This also enables the `LogSink::FILE` sink, leaving the other sinks untouched.
+/// As with `redirect_logs_to_file`, call `flush_logs` before the process exits.
+///
+/// # Arguments
+///
+/// * `node_id` - The node whose messages should be redirected.
+/// * `path` - The path of the file the node's messages are appended to.
+pub fn redirect_node_logs_to_file<P: AsRef<Path>>(node_id: NodeId, path: P) {
+    let _file_guard = LOG_FILE.lock().expect("Failed to get LOG_FILE lock");
+    let file = OpenOptions::new()
         .create(true)
         .append(true)
-        .open("network.log")
-        .expect("Failed to open log file"));
+        .open(path)
+        .expect("Failed to open node log file");
+    NODE_LOG_FILES
+        .lock()
+        .expect("Failed to get NODE_LOG_FILES lock")
+        .insert(node_id, BufWriter::new(file));
+    let mut sinks = LOG_SINKS.lock().expect("Failed to get LOG_SINKS lock");
+    *sinks = *sinks | LogSink::FILE;
+}
+
+/// Redirects log output to a channel.
+///
+/// This function disables stdout logging and sends every message logged through the
+/// logging macros as a `LogRecord` on `sender`. Sending never blocks: if the channel
+/// is full or disconnected, the record is dropped.
+///
+/// # Arguments
+///
+/// * `sender` - The channel the log records are sent to.
+pub fn redirect_logs_to_channel(sender: Sender<LogRecord>) {
+    disable_logging();
+    *LOG_CHANNEL.lock().expect("Failed to get LOG_CHANNEL lock") = Some(sender);
+}
+
+/// Sends every message logged through the logging macros to a custom destination.
+///
+/// The custom sink receives the messages in addition to the enabled built-in `LogSink`s;
+/// use `set_log_sinks(LogSink::NONE)` to only write to it. Like the built-in sinks, it does
+/// not receive the messages captured by `capture_logs` or sent to a log channel.
+/// The sink may itself log, e.g. to report an I/O error: those messages go to the other
+/// destinations only.
+///
+/// # Arguments
+///
+/// * `sink` - The custom destination, replacing any previous one.
+pub fn set_custom_log_sink(sink: Box<dyn LogWriter>) {
+    *CUSTOM_LOG_SINK.lock().expect("Failed to get CUSTOM_LOG_SINK lock") = Some(Arc::new(Mutex::new(sink)));
+}
+
+/// Removes the custom log destination set with `set_custom_log_sink`.
+pub fn clear_custom_log_sink() {
+    *CUSTOM_LOG_SINK.lock().expect("Failed to get CUSTOM_LOG_SINK lock") = None;
+}
+
+/// Captures log output in memory instead of printing or writing it.
+///
+/// While capture is active, every message logged through the logging macros is
+/// formatted and stored in an in-memory buffer, which can be retrieved with
+/// `take_captured_logs`. This is mostly useful to check what a node logs in tests.
+pub fn capture_logs() {
+    let mut captured = CAPTURED_LOGS.lock().expect("Failed to get CAPTURED_LOGS lock");
+    if captured.is_none() {
+        *captured = Some(Vec::new());
+    }
+}
+
+/// Stops capturing log output and discards any line not yet taken.
+pub fn stop_capturing_logs() {
+    *CAPTURED_LOGS.lock().expect("Failed to get CAPTURED_LOGS lock") = None;
+}
+
+/// Drains the captured log lines.
+///
+/// # Returns
+///
+/// The lines captured since `capture_logs` or the previous call, in logging order.
+/// The vector is empty if capture is not active.
+pub fn take_captured_logs() -> Vec<String> {
+    CAPTURED_LOGS
+        .lock()
+        .expect("Failed to get CAPTURED_LOGS lock")
+        .as_mut()
+        .map(std::mem::take)
+        .unwrap_or_default()
 }
 
 /// Returns whether logging to stdout is enabled.
@@ -45,67 +461,591 @@ pub fn redirect_logs_to_file() {
 ///
 /// `true` if logging to stdout is enabled, otherwise `false`.
 pub fn is_logging_enabled() -> bool {
-    *LOGGING_ENABLED.lock().expect("Failed to get LOGGING_ENABLED lock")
+    current_log_sinks().contains(LogSink::STDOUT)
+}
+
+/// Sets the minimum level a message must have to be logged.
+///
+/// Messages below this threshold are discarded. The default level is `LogLevel::Info`.
+///
+/// # Arguments
+///
+/// * `level` - The new minimum log level.
+pub fn set_log_level(level: LogLevel) {
+    *LOG_LEVEL.lock().expect("Failed to get LOG_LEVEL lock") = level;
+}
+
+/// Returns the current minimum log level.
+///
+/// Only takes the level lock, so it is cheap enough to be called from a node's main loop.
+pub fn current_log_level() -> LogLevel {
+    *LOG_LEVEL.lock().expect("Failed to get LOG_LEVEL lock")
+}
+
+/// Returns whether a message of the given level passes the current threshold.
+///
+/// # Arguments
+///
+/// * `level` - The level of the message about to be logged.
+pub fn is_level_enabled(level: LogLevel) -> bool {
+    level >= current_log_level()
+}
+
+/// Returns the number of errors logged by a node with `log_error!`.
+///
+/// Errors are counted even when they are filtered out by the log level or the node filter,
+/// so the count reflects the health of the node whatever the logging configuration.
+///
+/// # Arguments
+///
+/// * `node_id` - The node whose errors are counted.
+pub fn error_count(node_id: NodeId) -> u64 {
+    ERROR_COUNTS[usize::from(node_id)].load(Ordering::Relaxed)
+}
+
+/// Resets the error counts of every node to 0.
+pub fn reset_error_counts() {
+    for count in ERROR_COUNTS.iter() {
+        count.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Counts an error logged by a node, see `error_count`.
+#[doc(hidden)]
+pub fn count_error(node_id: NodeId) {
+    ERROR_COUNTS[usize::from(node_id)].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Sets the minimum log level of a single node, overriding the global level for it.
+///
+/// The override can be lower than the global level, to debug one node without the logs of
+/// the others, or higher, to silence a noisy node.
+///
+/// # Arguments
+///
+/// * `node_id` - The node whose level is overridden.
+/// * `level` - The minimum log level of the node.
+pub fn set_node_log_level(node_id: NodeId, level: LogLevel) {
+    NODE_LOG_LEVELS
+        .lock()
+        .expect("Failed to get NODE_LOG_LEVELS lock")
+        .insert(node_id, level);
+}
+
+/// Removes the log level override of a node, which follows the global level again.
+///
+/// # Arguments
+///
+/// * `node_id` - The node whose override is removed.
+pub fn clear_node_log_level(node_id: NodeId) {
+    NODE_LOG_LEVELS
+        .lock()
+        .expect("Failed to get NODE_LOG_LEVELS lock")
+        .remove(&node_id);
+}
+
+/// Returns whether a message of the given level from the given node passes its threshold:
+/// the node's override if it has one, the global level otherwise.
+///
+/// # Arguments
+///
+/// * `node_id` - The node about to log a message.
+/// * `level` - The level of the message about to be logged.
+pub fn is_level_enabled_for(node_id: NodeId, level: LogLevel) -> bool {
+    let node_level = NODE_LOG_LEVELS
+        .lock()
+        .expect("Failed to get NODE_LOG_LEVELS lock")
+        .get(&node_id)
+        .copied();
+    match node_level {
+        Some(node_level) => level >= node_level,
+        None => is_level_enabled(level),
+    }
+}
+
+/// Collapses the identical messages logged by a node within `window`.
+///
+/// The first message is written; the identical ones (same node, same text) logged within the
+/// window are suppressed and counted. The next identical message logged after the window is
+/// written with a `(repeated N times)` suffix, N being the number of suppressed messages, and
+/// starts a new window. Only applies to the logging macros.
+///
+/// # Arguments
+///
+/// * `window` - How long identical messages are suppressed after one is written.
+pub fn set_log_dedup_window(window: Duration) {
+    LOG_DEDUP.lock().expect("Failed to get LOG_DEDUP lock").window = Some(window);
+}
+
+/// Disables the deduplication of log messages, see `set_log_dedup_window`.
+pub fn disable_log_dedup() {
+    let mut dedup = LOG_DEDUP.lock().expect("Failed to get LOG_DEDUP lock");
+    dedup.window = None;
+    dedup.recent.clear();
+}
+
+/// Applies the deduplication window to a message about to be logged.
+///
+/// # Returns
+///
+/// `None` if the message must be suppressed, otherwise the message to write, with the number
+/// of suppressed identical messages appended if there were any.
+fn deduplicate(node_id: NodeId, message: String) -> Option<String> {
+    let mut dedup = LOG_DEDUP.lock().expect("Failed to get LOG_DEDUP lock");
+    let Some(window) = dedup.window else {
+        return Some(message);
+    };
+    let mut hasher = DefaultHasher::new();
+    message.hash(&mut hasher);
+    let key = (node_id, hasher.finish());
+    let now = Instant::now();
+
+    if let Some((written_at, suppressed)) = dedup.recent.get_mut(&key) {
+        if now.duration_since(*written_at) < window {
+            *suppressed += 1;
+            return None;
+        }
+        let repeated = std::mem::take(suppressed);
+        *written_at = now;
+        if repeated > 0 {
+            return Some(format!("{} (repeated {} times)", message, repeated));
+        }
+        return Some(message);
+    }
+
+    if dedup.recent.len() >= LOG_DEDUP_MAX_ENTRIES {
+        dedup.recent.retain(|_, (written_at, _)| now.duration_since(*written_at) < window);
+    }
+    dedup.recent.insert(key, (now, 0));
+    Some(message)
+}
+
+/// Enables or disables the coloring of the level tags printed to the console.
+///
+/// When enabled, the tags are wrapped in ANSI colors: green for info, yellow for warnings and
+/// red for errors. Colors are only used when the output stream is a terminal, so piped output
+/// stays plain; log files are never colored. Disabled by default. Has no effect with the
+/// `log-facade` feature, where the installed logger formats the messages.
+///
+/// # Arguments
+///
+/// * `enabled` - Whether to color the console output.
+pub fn set_log_colors(enabled: bool) {
+    *LOG_COLORS.lock().expect("Failed to get LOG_COLORS lock") = enabled;
+}
+
+/// Sets the format of the lines written to log files.
+///
+/// The default format is `LogFormat::Text`.
+///
+/// # Arguments
+///
+/// * `format` - The new log line format.
+pub fn set_log_format(format: LogFormat) {
+    *LOG_FORMAT.lock().expect("Failed to get LOG_FORMAT lock") = format;
+}
+
+/// Sets the time zone of the timestamps written to log files.
+///
+/// The default time zone is `LogTimezone::Local`.
+///
+/// # Arguments
+///
+/// * `timezone` - The new time zone of the timestamps.
+pub fn set_log_timezone(timezone: LogTimezone) {
+    *LOG_TIMEZONE.lock().expect("Failed to get LOG_TIMEZONE lock") = timezone;
+}
+
+/// Formats the timestamp of a log line in the given time zone.
+fn format_timestamp(now: DateTime<Utc>, timezone: LogTimezone) -> String {
+    match timezone {
+        LogTimezone::Local => now.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+        LogTimezone::Utc => now.format("%Y-%m-%d %H:%M:%S%.3fZ").to_string(),
+    }
+}
+
+/// Escapes a string so it can be embedded in a JSON string literal.
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats a log line, terminated by a newline, according to the current log format.
+fn format_log_line(node_id: NodeId, category: Option<&str>, message: &str, level: LogLevel) -> String {
+    let timezone = *LOG_TIMEZONE.lock().expect("Failed to get LOG_TIMEZONE lock");
+    let timestamp = format_timestamp(Utc::now(), timezone);
+    match *LOG_FORMAT.lock().expect("Failed to get LOG_FORMAT lock") {
+        LogFormat::Text => format!("[{}] [{:5}] [NODE {}] {}\n",
+            timestamp, level.as_str(), node_id, with_category_tag(category, message)),
+        LogFormat::Json => {
+            let category_field = category
+                .map(|category| format!("\"category\":\"{}\",", escape_json(category)))
+                .unwrap_or_default();
+            format!(
+                "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"node_id\":{},{}\"message\":\"{}\"}}\n",
+                timestamp, level.as_str(), node_id, category_field, escape_json(message))
+        }
+    }
+}
+
+/// Prefixes a message with the `[CATEGORY]` tag of its category, if it has one.
+fn with_category_tag(category: Option<&str>, message: &str) -> String {
+    match category {
+        Some(category) => format!("[{}] {}", category, message),
+        None => message.to_string(),
+    }
+}
+
+/// Restricts logging to the given nodes.
+///
+/// Messages from any node not in `allowed` are discarded. An empty set means
+/// every node is logged, like `clear_log_node_filter`.
+///
+/// # Arguments
+///
+/// * `allowed` - The nodes whose messages are logged.
+pub fn set_log_node_filter(allowed: HashSet<NodeId>) {
+    let mut filter = LOG_NODE_FILTER.lock().expect("Failed to get LOG_NODE_FILTER lock");
+    *filter = if allowed.is_empty() { None } else { Some(allowed) };
+}
+
+/// Removes the node filter so that every node is logged again.
+pub fn clear_log_node_filter() {
+    *LOG_NODE_FILTER.lock().expect("Failed to get LOG_NODE_FILTER lock") = None;
+}
+
+/// Returns whether messages from the given node pass the node filter.
+///
+/// # Arguments
+///
+/// * `node_id` - The node about to log a message.
+pub fn is_node_logged(node_id: NodeId) -> bool {
+    LOG_NODE_FILTER
+        .lock()
+        .expect("Failed to get LOG_NODE_FILTER lock")
+        .as_ref()
+        .is_none_or(|allowed| allowed.contains(&node_id))
 }
 
 /// Checks if a log file is currently configured for logging.
 ///
 /// # Returns
 ///
-/// `true` if a log file is set for logging, otherwise `false`.
+/// `true` if a shared or per-node log file is set for logging, otherwise `false`.
 pub fn has_log_file() -> bool {
-    LOG_FILE.lock().expect("Failed to get LOG_FILE lock").is_some()
+    let file_guard = LOG_FILE.lock().expect("Failed to get LOG_FILE lock");
+    file_guard.is_some()
+        || !NODE_LOG_FILES
+            .lock()
+            .expect("Failed to get NODE_LOG_FILES lock")
+            .is_empty()
 }
 
 /// Writes a log message to the log file if available.
 ///
+/// The message goes to the node's own file if one was configured with
+/// `redirect_node_logs_to_file`, and to the shared log file otherwise.
 /// The log message includes a timestamp, log level, node identifier,
 /// and the provided message. If writing fails, an error is printed to stderr.
+/// Warnings and errors are flushed immediately, other lines about once a second.
 ///
 /// # Arguments
 ///
 /// * `node_id` - Identifier for the node that is logging the message.
 /// * `message` - The log message to be written.
-/// * `is_error` - A flag indicating whether the message represents an error.
-pub fn write_to_log(node_id: u8, message: String, is_error: bool) {
-    if let Some(file) = LOG_FILE.lock().expect("Failed to get LOG_FILE lock").as_mut() {
-        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let level = if is_error { "ERROR" } else { "INFO" };
-        let log_line = format!("[{}] [{:5}] [NODE {}] {}\n", 
-            timestamp, level, node_id, message);
-        
+/// * `level` - The severity of the message. Messages below the level of the node (its
+///   override, or the global level) are dropped, as are messages from nodes excluded by the
+///   node filter.
+pub fn write_to_log(node_id: u8, message: String, level: LogLevel) {
+    write_categorized_to_log(node_id, None, &message, level);
+}
+
+/// Writes a log message of the given category to the log file, see `write_to_log`.
+///
+/// # Arguments
+///
+/// * `node_id` - Identifier for the node that is logging the message.
+/// * `category` - The category of the message, rendered as a `[CATEGORY]` tag, if any.
+/// * `message` - The log message to be written.
+/// * `level` - The severity of the message.
+pub fn write_categorized_to_log(node_id: NodeId, category: Option<&str>, message: &str, level: LogLevel) {
+    if !is_level_enabled_for(node_id, level) || !is_node_logged(node_id) {
+        return;
+    }
+    let mut file_guard = LOG_FILE.lock().expect("Failed to get LOG_FILE lock");
+    let mut node_files = NODE_LOG_FILES.lock().expect("Failed to get NODE_LOG_FILES lock");
+    let log_line = format_log_line(node_id, category, message, level);
+    let target = match node_files.get_mut(&node_id) {
+        Some(node_file) => Some(node_file),
+        None => file_guard.as_mut().map(|file| {
+            rotate_log_file_if_needed(file, log_line.len() as u64);
+            file
+        }),
+    };
+    if let Some(file) = target {
         if let Err(e) = file.write_all(log_line.as_bytes()) {
             eprintln!("Failed to write to log file: {}", e);
         }
+        // Warnings and errors must not wait for the next line to reach the disk
+        if level >= LogLevel::Warn {
+            if let Err(e) = file.flush() {
+                eprintln!("Failed to flush log file: {}", e);
+            }
+        }
+    }
+
+    // Lines are buffered: flush every file once in a while, the locks being already held
+    let mut last_flush = LAST_LOG_FLUSH.lock().expect("Failed to get LAST_LOG_FLUSH lock");
+    if last_flush.elapsed() >= LOG_FLUSH_INTERVAL {
+        *last_flush = Instant::now();
+        for file in file_guard.iter_mut().chain(node_files.values_mut()) {
+            if let Err(e) = file.flush() {
+                eprintln!("Failed to flush log file: {}", e);
+            }
+        }
+    }
+}
+
+/// Flushes the shared log file and the per-node log files.
+///
+/// Log lines below `LogLevel::Warn` are buffered and only flushed every second or so, when
+/// another line is written: call this before a node stops, e.g. when it crashes, and before
+/// the process exits, so that the last lines written are durable.
+/// Errors are reported on stderr.
+pub fn flush_logs() {
+    if let Some(file) = LOG_FILE.lock().expect("Failed to get LOG_FILE lock").as_mut() {
+        if let Err(e) = file.flush() {
+            eprintln!("Failed to flush log file: {}", e);
+        }
+    }
+    for file in NODE_LOG_FILES
+        .lock()
+        .expect("Failed to get NODE_LOG_FILES lock")
+        .values_mut()
+    {
+        if let Err(e) = file.flush() {
+            eprintln!("Failed to flush node log file: {}", e);
+        }
+    }
+}
+
+/// Returns whether a message from `node_id` at `level` would be logged anywhere.
+///
+/// The logging macros call this before formatting their arguments, so that
+/// nothing is evaluated when the message would be discarded anyway.
+#[doc(hidden)]
+pub fn should_log(node_id: NodeId, level: LogLevel) -> bool {
+    if !is_level_enabled_for(node_id, level) || !is_node_logged(node_id) {
+        return false;
+    }
+    // With the `tracing` feature, every message is also recorded as a tracing event, but only
+    // a subscriber interested in it makes formatting worthwhile
+    #[cfg(feature = "tracing")]
+    if is_tracing_enabled(level) {
+        return true;
+    }
+    if CAPTURED_LOGS.lock().expect("Failed to get CAPTURED_LOGS lock").is_some()
+        || LOG_CHANNEL.lock().expect("Failed to get LOG_CHANNEL lock").is_some()
+        || CUSTOM_LOG_SINK.lock().expect("Failed to get CUSTOM_LOG_SINK lock").is_some()
+    {
+        return true;
+    }
+    let sinks = current_log_sinks();
+    sinks.contains(LogSink::STDOUT) || (sinks.contains(LogSink::FILE) && has_log_file())
+}
+
+/// Dispatches a message logged through the logging macros to the active destination.
+///
+/// The message goes to the capture buffer if `capture_logs` is active, otherwise to the
+/// log channel if one is configured, otherwise to every enabled `LogSink` and to the custom
+/// sink set with `set_custom_log_sink`. With the `tracing` feature, it is also recorded as an event of the current tracing span.
+/// Identical messages are first collapsed if `set_log_dedup_window` is set.
+#[doc(hidden)]
+pub fn dispatch_log(node_id: NodeId, level: LogLevel, category: Option<&'static str>, message: String) {
+    if !is_level_enabled_for(node_id, level) || !is_node_logged(node_id) {
+        return;
+    }
+    let Some(message) = deduplicate(node_id, message) else {
+        return;
+    };
+    #[cfg(feature = "tracing")]
+    emit_tracing_event(node_id, level, &message);
+    if let Some(captured) = CAPTURED_LOGS
+        .lock()
+        .expect("Failed to get CAPTURED_LOGS lock")
+        .as_mut()
+    {
+        captured.push(format_log_line(node_id, category, &message, level).trim_end().to_string());
+        return;
+    }
+    let record = LogRecord {
+        node_id,
+        level,
+        category,
+        timestamp: Local::now(),
+        message,
+    };
+    if let Some(sender) = LOG_CHANNEL.lock().expect("Failed to get LOG_CHANNEL lock").as_ref() {
+        // Never block the node thread on a slow consumer: drop the record instead
+        let _ = sender.try_send(record);
+        return;
+    }
+    let sinks = current_log_sinks();
+    if sinks.contains(LogSink::STDOUT) {
+        ConsoleLogWriter.write(&record);
+    }
+    if sinks.contains(LogSink::FILE) {
+        FileLogWriter.write(&record);
+    }
+    // The sink is written without holding CUSTOM_LOG_SINK, and messages it logs itself skip it,
+    // so that a sink that logs does not deadlock
+    let custom_sink = CUSTOM_LOG_SINK.lock().expect("Failed to get CUSTOM_LOG_SINK lock").clone();
+    if let Some(sink) = custom_sink {
+        if !WRITING_CUSTOM_LOG.with(Cell::get) {
+            WRITING_CUSTOM_LOG.with(|writing| writing.set(true));
+            sink.lock().unwrap_or_else(|e| e.into_inner()).write(&record);
+            WRITING_CUSTOM_LOG.with(|writing| writing.set(false));
+        }
+    }
+}
+
+/// Returns whether the current tracing subscriber wants events at `level`.
+///
+/// Without any subscriber, nothing is recorded and this returns `false`.
+#[cfg(feature = "tracing")]
+fn is_tracing_enabled(level: LogLevel) -> bool {
+    match level {
+        LogLevel::Trace => tracing::enabled!(tracing::Level::TRACE),
+        LogLevel::Debug => tracing::enabled!(tracing::Level::DEBUG),
+        LogLevel::Info => tracing::enabled!(tracing::Level::INFO),
+        LogLevel::Warn => tracing::enabled!(tracing::Level::WARN),
+        LogLevel::Error => tracing::enabled!(tracing::Level::ERROR),
+    }
+}
+
+/// Records a message as an event of the current tracing span, e.g. the span of
+/// `NetworkNode::forward_packet`, in addition to the configured destinations.
+#[cfg(feature = "tracing")]
+fn emit_tracing_event(node_id: NodeId, level: LogLevel, message: &str) {
+    match level {
+        LogLevel::Trace => tracing::trace!(node_id, "{}", message),
+        LogLevel::Debug => tracing::debug!(node_id, "{}", message),
+        LogLevel::Info => tracing::info!(node_id, "{}", message),
+        LogLevel::Warn => tracing::warn!(node_id, "{}", message),
+        LogLevel::Error => tracing::error!(node_id, "{}", message),
+    }
+}
+
+/// Prints a message to stdout, or to stderr for warnings and errors.
+///
+/// The level tag is colored if `set_log_colors` is enabled and the stream is a terminal.
+#[cfg(not(feature = "log-facade"))]
+fn write_to_console(node_id: NodeId, level: LogLevel, message: &str) {
+    use std::io::IsTerminal;
+
+    let to_stderr = level >= LogLevel::Warn;
+    let colored = *LOG_COLORS.lock().expect("Failed to get LOG_COLORS lock")
+        && if to_stderr {
+            std::io::stderr().is_terminal()
+        } else {
+            std::io::stdout().is_terminal()
+        };
+    let line = format_console_line(node_id, level, message, colored);
+    if to_stderr {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Formats a console line, with the level tag wrapped in ANSI colors if `colored` is set.
+///
+/// Info messages have no tag, unless colored: they are then tagged `Info:` in green.
+#[cfg(not(feature = "log-facade"))]
+fn format_console_line(node_id: NodeId, level: LogLevel, message: &str, colored: bool) -> String {
+    let (tag, color) = match level {
+        LogLevel::Trace => ("Trace:", None),
+        LogLevel::Debug => ("Debug:", None),
+        LogLevel::Info if !colored => return format!("[NODE {}] {}", node_id, message),
+        LogLevel::Info => ("Info:", Some("32")),
+        LogLevel::Warn => ("Warning:", Some("33")),
+        LogLevel::Error => ("Error:", Some("31")),
+    };
+    match color {
+        Some(color) if colored => format!("[NODE {}] \x1b[{}m{}\x1b[0m {}", node_id, color, tag, message),
+        _ => format!("[NODE {}] {} {}", node_id, tag, message),
+    }
+}
+
+/// Hands a message to the `log` crate facade, so that the logger installed by the application
+/// (e.g. `env_logger`, configured through `RUST_LOG`) decides where it goes.
+///
+/// The records use the `dr_ones` target and keep the `[NODE id]` prefix of the console output.
+#[cfg(feature = "log-facade")]
+fn write_to_console(node_id: NodeId, level: LogLevel, message: &str) {
+    log::log!(target: "dr_ones", level.into(), "[NODE {}] {}", node_id, message);
+}
+
+#[cfg(feature = "log-facade")]
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => log::Level::Trace,
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Error => log::Level::Error,
+        }
     }
 }
 
 #[macro_export]
 /// Logs a status message.
 ///
-/// If logging to stdout is enabled, the message is printed to stdout.
-/// Otherwise, if a log file is configured, the message is written to the file.
+/// If log capture is active, the message is stored in the capture buffer.
+/// Otherwise, if a log channel is configured, the message is sent on it.
+/// Otherwise, the message is printed to stdout and/or written to the log file,
+/// depending on the enabled log sinks.
 ///
 /// # Examples
 ///
 /// ```
 /// log_status!(1, "Node is online");
+/// log_status!(1, category::FLOOD, "Flood {} initiated", 7);
 /// ```
 macro_rules! log_status {
-    ($node_id:expr, $($arg:tt)*) => {
-        if $crate::logging::is_logging_enabled() {
-            println!("[NODE {}] {}", $node_id, format!($($arg)*));
-        } else if $crate::logging::has_log_file() {
-            $crate::logging::write_to_log($node_id, format!($($arg)*), false);
+    ($node_id:expr, $category:path, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Info) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Info, Some($category), format!($($arg)*));
         }
-    };
+    }};
+    ($node_id:expr, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Info) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Info, None, format!($($arg)*));
+        }
+    }};
 }
 
 #[macro_export]
 /// Logs an error message.
 ///
-/// If logging to stdout is enabled, the message is printed to stderr.
-/// Otherwise, if a log file is configured, the message is written to the file as an error.
+/// If log capture is active, the message is stored in the capture buffer.
+/// Otherwise, if a log channel is configured, the message is sent on it.
+/// Otherwise, the message is printed to stderr and/or written to the log file as an error,
+/// depending on the enabled log sinks. The error is counted in any case, see `error_count`.
 ///
 /// # Examples
 ///
@@ -113,13 +1053,104 @@ macro_rules! log_status {
 /// log_error!(1, "Failed to connect to the server");
 /// ```
 macro_rules! log_error {
-    ($node_id:expr, $($arg:tt)*) => {
-        if $crate::logging::is_logging_enabled() {
-            eprintln!("[NODE {}] Error: {}", $node_id, format!($($arg)*));
-        } else if $crate::logging::has_log_file() {
-            $crate::logging::write_to_log($node_id, format!($($arg)*), true);
+    ($node_id:expr, $category:path, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        $crate::logging::count_error(node_id);
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Error) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Error, Some($category), format!($($arg)*));
         }
-    };
+    }};
+    ($node_id:expr, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        $crate::logging::count_error(node_id);
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Error) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Error, None, format!($($arg)*));
+        }
+    }};
+}
+
+#[macro_export]
+/// Logs a warning message.
+///
+/// If log capture is active, the message is stored in the capture buffer.
+/// Otherwise, if a log channel is configured, the message is sent on it.
+/// Otherwise, the message is printed to stderr and/or written to the log file as a warning,
+/// depending on the enabled log sinks.
+///
+/// # Examples
+///
+/// ```
+/// log_warn!(1, "Neighbour 3 is not responding");
+/// ```
+macro_rules! log_warn {
+    ($node_id:expr, $category:path, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Warn) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Warn, Some($category), format!($($arg)*));
+        }
+    }};
+    ($node_id:expr, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Warn) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Warn, None, format!($($arg)*));
+        }
+    }};
+}
+
+#[macro_export]
+/// Logs a debug message.
+///
+/// If log capture is active, the message is stored in the capture buffer.
+/// Otherwise, if a log channel is configured, the message is sent on it.
+/// Otherwise, the message is printed to stdout and/or written to the log file as debug output,
+/// depending on the enabled log sinks.
+///
+/// # Examples
+///
+/// ```
+/// log_debug!(1, "Broadcasting flood request to {} neighbours", 3);
+/// ```
+macro_rules! log_debug {
+    ($node_id:expr, $category:path, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Debug) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Debug, Some($category), format!($($arg)*));
+        }
+    }};
+    ($node_id:expr, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Debug) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Debug, None, format!($($arg)*));
+        }
+    }};
+}
+
+#[macro_export]
+/// Logs a trace message.
+///
+/// If log capture is active, the message is stored in the capture buffer.
+/// Otherwise, if a log channel is configured, the message is sent on it.
+/// Otherwise, the message is printed to stdout and/or written to the log file as trace output,
+/// depending on the enabled log sinks.
+///
+/// # Examples
+///
+/// ```
+/// log_trace!(1, "Received packet with session id {}", 42);
+/// ```
+macro_rules! log_trace {
+    ($node_id:expr, $category:path, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Trace) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Trace, Some($category), format!($($arg)*));
+        }
+    }};
+    ($node_id:expr, $($arg:tt)*) => {{
+        let node_id = $node_id;
+        if $crate::logging::should_log(node_id, $crate::logging::LogLevel::Trace) {
+            $crate::logging::dispatch_log(node_id, $crate::logging::LogLevel::Trace, None, format!($($arg)*));
+        }
+    }};
 }
 
 #[cfg(test)]
@@ -128,8 +1159,12 @@ mod tests {
     use std::fs;
     use std::path::Path;
 
+    /// Serializes the tests of this module, since they all share the global logging state.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
     #[test]
     fn test_enable_disable() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         enable_logging();
         assert!(is_logging_enabled());
         disable_logging();
@@ -139,6 +1174,7 @@ mod tests {
 
     #[test]
     fn test_disable_with_file() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         let log_path = Path::new("network.log");
         if log_path.exists() {
             fs::remove_file(log_path).expect("Failed to remove log file");
@@ -147,8 +1183,411 @@ mod tests {
         redirect_logs_to_file();
         assert!(!is_logging_enabled());
         assert!(has_log_file());
-        write_to_log(1, "Test message".to_string(), false);
+        write_to_log(1, "Test message".to_string(), LogLevel::Info);
         assert!(log_path.exists());
         fs::remove_file(log_path).expect("Failed to remove log file");
     }
+
+    #[test]
+    fn test_node_log_file() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let node_log_path = Path::new("node_200.log");
+        if node_log_path.exists() {
+            fs::remove_file(node_log_path).expect("Failed to remove node log file");
+        }
+
+        redirect_node_logs_to_file(200, node_log_path);
+        assert!(has_log_file());
+        write_to_log(200, "Per-node message".to_string(), LogLevel::Error);
+        flush_logs();
+        let content = fs::read_to_string(node_log_path).expect("Failed to read node log file");
+        assert!(content.contains("[NODE 200] Per-node message"));
+        enable_logging();
+        fs::remove_file(node_log_path).expect("Failed to remove node log file");
+    }
+
+    #[test]
+    fn test_log_rotation() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for path in ["network.log", "network.log.1", "network.log.2"] {
+            let _ = fs::remove_file(path);
+        }
+
+        redirect_logs_to_file();
+        set_log_rotation(64);
+        set_log_rotation_keep(1);
+        for i in 0..4 {
+            write_to_log(1, format!("Rotation test message number {}", i), LogLevel::Error);
+        }
+        disable_log_rotation();
+        set_log_rotation_keep(DEFAULT_ROTATION_KEEP);
+        enable_logging();
+
+        assert!(Path::new("network.log").exists());
+        assert!(Path::new("network.log.1").exists());
+        assert!(!Path::new("network.log.2").exists());
+        let rotated = fs::read_to_string("network.log.1").expect("Failed to read rotated log file");
+        assert!(rotated.contains("number 2"));
+        for path in ["network.log", "network.log.1"] {
+            fs::remove_file(path).expect("Failed to remove log file");
+        }
+    }
+
+    #[test]
+    fn test_json_format() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_log_format(LogFormat::Json);
+        let line = format_log_line(3, None, "say \"hi\"\nbye", LogLevel::Warn);
+        let categorized = format_log_line(3, Some(category::FLOOD), "flooding", LogLevel::Warn);
+        set_log_format(LogFormat::Text);
+
+        assert!(line.starts_with("{\"timestamp\":\""));
+        assert!(line.contains("\"level\":\"WARN\",\"node_id\":3,"));
+        assert!(line.ends_with("\"message\":\"say \\\"hi\\\"\\nbye\"}\n"));
+        assert!(!line.contains("\"category\""));
+        assert!(categorized.contains("\"node_id\":3,\"category\":\"FLOOD\",\"message\":\"flooding\"}"));
+        assert_eq!(line.matches('\n').count(), 1);
+    }
+
+    #[test]
+    fn test_capture_logs() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        capture_logs();
+        crate::log_status!(7, "No channel found for next hop: {:?}", 3);
+        crate::log_error!(7, "Something went wrong");
+        let logs = take_captured_logs();
+        stop_capturing_logs();
+
+        // Tests of other modules can log concurrently: only look at the lines of this one
+        let logs: Vec<&String> = logs
+            .iter()
+            .filter(|l| {
+                l.contains("[NODE 7] No channel found for next hop: 3")
+                    || l.contains("[NODE 7] Something went wrong")
+            })
+            .collect();
+        assert_eq!(logs.len(), 2);
+        assert!(logs[0].contains("[INFO ] [NODE 7] No channel found for next hop: 3"));
+        assert!(logs[1].contains("[ERROR] [NODE 7] Something went wrong"));
+        assert!(take_captured_logs().is_empty());
+    }
+
+    #[test]
+    fn test_log_channel() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        redirect_logs_to_channel(sender);
+        crate::log_warn!(4, "First warning");
+        // The channel is full: this record must be dropped without blocking
+        crate::log_warn!(4, "Second warning");
+        enable_logging();
+
+        let record = receiver.try_recv().expect("Failed to receive log record");
+        assert_eq!(record.node_id, 4);
+        assert_eq!(record.level, LogLevel::Warn);
+        assert_eq!(record.message, "First warning");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_tee_sinks() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let log_path = Path::new("network.log");
+        if log_path.exists() {
+            fs::remove_file(log_path).expect("Failed to remove log file");
+        }
+
+        enable_logging();
+        set_log_sinks(LogSink::STDOUT | LogSink::FILE);
+        assert!(is_logging_enabled());
+        assert!(has_log_file());
+        crate::log_status!(9, "Teed message");
+        enable_logging();
+        assert_eq!(current_log_sinks(), LogSink::STDOUT);
+
+        let content = fs::read_to_string(log_path).expect("Failed to read log file");
+        assert!(content.contains("[NODE 9] Teed message"));
+        fs::remove_file(log_path).expect("Failed to remove log file");
+    }
+
+    #[test]
+    fn test_log_node_filter() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        capture_logs();
+        set_log_node_filter(HashSet::from([5]));
+        crate::log_status!(5, "Allowed node");
+        crate::log_status!(6, "Filtered node");
+        clear_log_node_filter();
+        crate::log_status!(6, "Unfiltered node");
+        let logs = take_captured_logs();
+        stop_capturing_logs();
+
+        assert!(logs.iter().any(|l| l.contains("[NODE 5] Allowed node")));
+        assert!(logs.iter().any(|l| l.contains("[NODE 6] Unfiltered node")));
+        assert!(!logs.iter().any(|l| l.contains("Filtered node")));
+    }
+
+    #[test]
+    fn test_lazy_argument_evaluation() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let calls = Cell::new(0);
+        let expensive_dump = || {
+            calls.set(calls.get() + 1);
+            "dump"
+        };
+
+        enable_logging();
+        disable_logging();
+        crate::log_status!(1, "state: {}", expensive_dump());
+        crate::log_error!(1, "state: {}", expensive_dump());
+        assert_eq!(calls.get(), 0);
+
+        enable_logging();
+        set_log_level(LogLevel::Error);
+        crate::log_debug!(1, "state: {}", expensive_dump());
+        set_log_level(LogLevel::Info);
+        assert_eq!(calls.get(), 0);
+
+        capture_logs();
+        crate::log_status!(1, "state: {}", expensive_dump());
+        stop_capturing_logs();
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_log_level_filtering() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_log_level(LogLevel::Warn);
+        assert!(!is_level_enabled(LogLevel::Info));
+        assert!(!is_level_enabled(LogLevel::Debug));
+        assert!(is_level_enabled(LogLevel::Warn));
+        assert!(is_level_enabled(LogLevel::Error));
+        set_log_level(LogLevel::Info);
+        assert!(is_level_enabled(LogLevel::Info));
+        assert!(!is_level_enabled(LogLevel::Trace));
+    }
+
+    #[test]
+    fn test_current_log_configuration() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        enable_logging();
+
+        set_log_sinks(LogSink::NONE);
+        set_log_level(LogLevel::Warn);
+        assert_eq!(current_log_sinks(), LogSink::NONE);
+        assert_eq!(current_log_level(), LogLevel::Warn);
+
+        set_log_sinks(LogSink::STDOUT);
+        set_log_level(LogLevel::Debug);
+        assert_eq!(current_log_sinks(), LogSink::STDOUT);
+        assert_eq!(current_log_level(), LogLevel::Debug);
+
+        set_log_level(LogLevel::Info);
+        enable_logging();
+    }
+
+    #[test]
+    fn test_flush_logs() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let node_log_path = Path::new("node_201.log");
+        let _ = fs::remove_file(node_log_path);
+
+        redirect_node_logs_to_file(201, node_log_path);
+        write_to_log(201, "Flushed message".to_string(), LogLevel::Info);
+        flush_logs();
+        // The file is still open: the line must be readable without closing it
+        let content = fs::read_to_string(node_log_path).expect("Failed to read node log file");
+        assert!(content.contains("[NODE 201] Flushed message"));
+
+        enable_logging();
+        fs::remove_file(node_log_path).expect("Failed to remove node log file");
+    }
+
+    #[test]
+    fn test_warnings_are_flushed_immediately() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let node_log_path = Path::new("node_202.log");
+        let _ = fs::remove_file(node_log_path);
+
+        redirect_node_logs_to_file(202, node_log_path);
+        write_to_log(202, "Unflushed warning".to_string(), LogLevel::Warn);
+        // No `flush_logs`: the warning must already be on disk
+        let content = fs::read_to_string(node_log_path).expect("Failed to read node log file");
+        assert!(content.contains("[NODE 202] Unflushed warning"));
+
+        enable_logging();
+        fs::remove_file(node_log_path).expect("Failed to remove node log file");
+    }
+
+    #[test]
+    fn test_node_log_level_override() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_log_level(LogLevel::Warn);
+        set_node_log_level(3, LogLevel::Debug);
+        set_node_log_level(5, LogLevel::Error);
+        assert!(is_level_enabled_for(3, LogLevel::Debug));
+        assert!(!is_level_enabled_for(4, LogLevel::Debug));
+        assert!(!is_level_enabled_for(5, LogLevel::Warn));
+
+        capture_logs();
+        crate::log_debug!(3, "zoomed in");
+        crate::log_debug!(4, "still filtered");
+        crate::log_warn!(5, "silenced");
+        let captured = take_captured_logs();
+        stop_capturing_logs();
+        // Tests of other modules may log concurrently: only look for these messages
+        assert!(captured.iter().any(|line| line.contains("zoomed in")));
+        assert!(!captured.iter().any(|line| line.contains("still filtered") || line.contains("silenced")));
+
+        clear_node_log_level(3);
+        clear_node_log_level(5);
+        assert!(!is_level_enabled_for(3, LogLevel::Debug));
+        assert!(is_level_enabled_for(5, LogLevel::Warn));
+        set_log_level(LogLevel::Info);
+    }
+
+    #[cfg(not(feature = "log-facade"))]
+    #[test]
+    fn test_console_line_colors() {
+        assert_eq!(format_console_line(1, LogLevel::Info, "up", false), "[NODE 1] up");
+        assert_eq!(format_console_line(1, LogLevel::Error, "down", false), "[NODE 1] Error: down");
+        assert_eq!(format_console_line(1, LogLevel::Info, "up", true), "[NODE 1] \x1b[32mInfo:\x1b[0m up");
+        assert_eq!(format_console_line(1, LogLevel::Warn, "slow", true), "[NODE 1] \x1b[33mWarning:\x1b[0m slow");
+        assert_eq!(format_console_line(1, LogLevel::Error, "down", true), "[NODE 1] \x1b[31mError:\x1b[0m down");
+        assert_eq!(format_console_line(1, LogLevel::Debug, "state", true), "[NODE 1] Debug: state");
+    }
+
+    #[test]
+    fn test_log_dedup() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_log_dedup_window(Duration::from_millis(200));
+        capture_logs();
+        for _ in 0..100 {
+            crate::log_error!(87, "No channel found for next hop: 9");
+        }
+        std::thread::sleep(Duration::from_millis(250));
+        crate::log_error!(87, "No channel found for next hop: 9");
+        let captured = take_captured_logs();
+        stop_capturing_logs();
+        disable_log_dedup();
+
+        let lines: Vec<&String> = captured
+            .iter()
+            .filter(|line| line.contains("[NODE 87] No channel found for next hop: 9"))
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with("(repeated 99 times)"));
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        use chrono::TimeZone;
+
+        let instant = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let utc = format_timestamp(instant, LogTimezone::Utc);
+        let local = format_timestamp(instant, LogTimezone::Local);
+        assert_eq!(utc, "2024-01-01 12:00:00.000Z");
+        assert!(!local.ends_with('Z'));
+
+        let offset = instant.with_timezone(&Local).offset().local_minus_utc();
+        if offset == 0 {
+            assert_eq!(local, "2024-01-01 12:00:00.000");
+        } else {
+            assert_ne!(local, "2024-01-01 12:00:00.000");
+        }
+    }
+
+    #[test]
+    fn test_log_categories() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        capture_logs();
+        crate::log_status!(89, "plain {}", 1);
+        crate::log_error!(89, category::FLOOD, "flood {}", 7);
+        crate::log_status!(89, category::ROUTING, "no arguments");
+        let captured = take_captured_logs();
+        stop_capturing_logs();
+
+        assert!(captured.iter().any(|line| line.ends_with("[NODE 89] plain 1")));
+        assert!(captured.iter().any(|line| line.ends_with("[NODE 89] [FLOOD] flood 7")));
+        assert!(captured.iter().any(|line| line.ends_with("[NODE 89] [ROUTING] no arguments")));
+    }
+
+    #[test]
+    fn test_error_count() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        reset_error_counts();
+        set_log_sinks(LogSink::NONE);
+        // Node ids no other test uses, since the counts are shared by the whole process
+        for attempt in 0..3 {
+            crate::log_error!(250, "Failed attempt {}", attempt);
+        }
+        crate::log_status!(250, "Not an error");
+        enable_logging();
+
+        assert_eq!(error_count(250), 3);
+        assert_eq!(error_count(251), 0);
+        reset_error_counts();
+        assert_eq!(error_count(250), 0);
+    }
+
+    /// Collects the messages it receives.
+    struct CollectingSink(Arc<Mutex<Vec<LogRecord>>>);
+
+    impl LogWriter for CollectingSink {
+        fn write(&mut self, record: &LogRecord) {
+            self.0.lock().expect("Failed to get the collected records lock").push(record.clone());
+        }
+    }
+
+    #[test]
+    fn test_custom_log_sink() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let collected = Arc::new(Mutex::new(Vec::new()));
+        set_log_sinks(LogSink::NONE);
+        set_custom_log_sink(Box::new(CollectingSink(Arc::clone(&collected))));
+
+        crate::log_warn!(91, category::ROUTING, "to the custom sink");
+        clear_custom_log_sink();
+        crate::log_warn!(91, "after clearing");
+        enable_logging();
+
+        let collected = collected.lock().expect("Failed to get the collected records lock");
+        let records: Vec<&LogRecord> = collected.iter().filter(|record| record.node_id == 91).collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].message, "to the custom sink");
+        assert_eq!(records[0].level, LogLevel::Warn);
+        assert_eq!(records[0].category, Some(category::ROUTING));
+    }
+
+    /// A sink reporting every record it writes through the logging macros.
+    struct LoggingSink(Arc<Mutex<Vec<LogRecord>>>);
+
+    impl LogWriter for LoggingSink {
+        fn write(&mut self, record: &LogRecord) {
+            self.0.lock().expect("Failed to get the collected records lock").push(record.clone());
+            crate::log_error!(record.node_id, "Custom sink wrote: {}", record.message);
+        }
+    }
+
+    #[test]
+    fn test_custom_log_sink_can_log() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let collected = Arc::new(Mutex::new(Vec::new()));
+        set_log_sinks(LogSink::NONE);
+        set_custom_log_sink(Box::new(LoggingSink(Arc::clone(&collected))));
+
+        // Would deadlock if the sink were written while holding its lock
+        crate::log_warn!(92, "to the logging sink");
+        clear_custom_log_sink();
+        enable_logging();
+
+        // The message logged by the sink itself is not written back to it
+        let collected = collected.lock().expect("Failed to get the collected records lock");
+        let messages: Vec<&str> = collected
+            .iter()
+            .filter(|record| record.node_id == 92)
+            .map(|record| record.message.as_str())
+            .collect();
+        assert_eq!(messages, vec!["to the logging sink"]);
+    }
 }
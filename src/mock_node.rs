@@ -0,0 +1,171 @@
+//! Ready-made `NetworkNode` implementation for the tests of the crates using this one.
+//!
+//! Available with the `test-util` feature.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use wg_2024::{
+    controller::DroneEvent,
+    network::NodeId,
+    packet::{NodeType, Packet},
+};
+
+use crate::network_node::{Command, NetworkNode, NodeMetrics};
+use crate::node_core::NodeCore;
+use crate::seen_flood_cache::SeenFloodIds;
+
+/// A network node that records what it does instead of talking to real neighbours.
+///
+/// Every neighbour added with `with_neighbors` shares a single capture channel, so the packets
+/// the node sends to any of them can be inspected, in order, with `forwarded_packets`; the
+/// routing header of each packet tells which neighbour it was sent to. The simulation events
+/// are kept as well, and routed packets handed to the node are stored without being processed.
+/// The random generator is seeded, so that tests relying on it are reproducible.
+pub struct MockNode {
+    core: NodeCore,
+    node_type: NodeType,
+    captured_send: Sender<Packet>,
+    captured_recv: Receiver<Packet>,
+    forwarded: Vec<Packet>,
+    sim_contr_recv: Receiver<DroneEvent>,
+    routed: Vec<Packet>,
+}
+
+impl MockNode {
+    /// Creates a node without neighbours.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the node.
+    /// * `node_type` - The type the node reports, e.g. in flood path traces.
+    pub fn new(id: NodeId, node_type: NodeType) -> Self {
+        let (sim_contr_send, sim_contr_recv) = unbounded();
+        let mut core = NodeCore::new(id, unbounded().1, sim_contr_send);
+        core.rng = StdRng::seed_from_u64(u64::from(id));
+        let (captured_send, captured_recv) = unbounded();
+        Self {
+            core,
+            node_type,
+            captured_send,
+            captured_recv,
+            forwarded: Vec::new(),
+            sim_contr_recv,
+            routed: Vec::new(),
+        }
+    }
+
+    /// Adds neighbours whose packets are captured.
+    ///
+    /// # Arguments
+    ///
+    /// * `neighbors` - The ids of the neighbours.
+    pub fn with_neighbors(mut self, neighbors: &[NodeId]) -> Self {
+        for &neighbor in neighbors {
+            self.core.packet_send.insert(neighbor, self.captured_send.clone());
+        }
+        self
+    }
+
+    /// Returns every packet sent by the node to its neighbours so far, in sending order.
+    pub fn forwarded_packets(&mut self) -> &[Packet] {
+        self.forwarded.extend(self.captured_recv.try_iter());
+        &self.forwarded
+    }
+
+    /// Returns the simulation events sent by the node since the last call.
+    pub fn take_sim_events(&self) -> Vec<DroneEvent> {
+        self.sim_contr_recv.try_iter().collect()
+    }
+
+    /// Returns the packets handed to `handle_routed_packet`, in order.
+    pub fn routed_packets(&self) -> &[Packet] {
+        &self.routed
+    }
+}
+
+impl NetworkNode for MockNode {
+    fn get_id(&self) -> NodeId {
+        self.core.id
+    }
+
+    fn get_node_type(&self) -> NodeType {
+        self.node_type
+    }
+
+    fn get_seen_flood_ids(&mut self) -> &mut dyn SeenFloodIds {
+        &mut self.core.seen_flood_ids
+    }
+
+    fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>> {
+        &mut self.core.packet_send
+    }
+
+    fn packet_send_ref(&self) -> &HashMap<NodeId, Sender<Packet>> {
+        &self.core.packet_send
+    }
+
+    fn get_packet_receiver(&self) -> &Receiver<Packet> {
+        &self.core.packet_recv
+    }
+
+    fn get_random_generator(&mut self) -> &mut dyn RngCore {
+        &mut self.core.rng
+    }
+
+    fn get_sim_contr_send(&self) -> &Sender<DroneEvent> {
+        &self.core.sim_contr_send
+    }
+
+    fn get_metrics(&mut self) -> &mut NodeMetrics {
+        &mut self.core.metrics
+    }
+
+    fn get_last_flood_id(&mut self) -> &mut u64 {
+        &mut self.core.last_flood_id
+    }
+
+    fn broadcast_blocklist_ref(&self) -> Option<&HashSet<NodeId>> {
+        Some(&self.core.broadcast_blocklist)
+    }
+
+    fn get_broadcast_blocklist(&mut self) -> Option<&mut HashSet<NodeId>> {
+        Some(&mut self.core.broadcast_blocklist)
+    }
+
+    /// Stores the packet, see `routed_packets`.
+    fn handle_routed_packet(&mut self, packet: Packet) -> bool {
+        self.routed.push(packet);
+        true
+    }
+
+    /// Ignores the command.
+    fn handle_command(&mut self, _command: Command) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network_node::fragment_message;
+    use wg_2024::network::SourceRoutingHeader;
+
+    #[test]
+    fn test_mock_node_captures_forwarded_packets() {
+        let mut node = MockNode::new(2, NodeType::Drone).with_neighbors(&[1, 3]);
+        let mut packet = fragment_message(&[1, 2, 3], 8).remove(0);
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2, 3],
+        };
+
+        node.forward_packet(packet).expect("Failed to forward packet");
+        assert!(node.forward_packet(fragment_message(&[4], 9).remove(0)).is_err());
+
+        let forwarded = node.forwarded_packets();
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0].session_id, 8);
+        assert_eq!(forwarded[0].routing_header.hop_index, 2);
+        assert!(matches!(node.take_sim_events()[..], [DroneEvent::PacketSent(_)]));
+    }
+}
@@ -1,26 +1,105 @@
 //! Network utilities module.
 //! Provides common functionality for network nodes (drones, clients, and servers).
 
-use crossbeam_channel::{Receiver, Sender};
-use rand::rngs::StdRng;
+use bincode::Options;
+use crossbeam_channel::{Receiver, RecvError, Select, Sender};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use rand::{Rng, RngCore};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use wg_2024::{
     controller::{DroneCommand, DroneEvent},
     network::{NodeId, SourceRoutingHeader},
-    packet::{Ack, Nack, NackType, NodeType, Packet, PacketType},
+    packet::{Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType},
 };
 
+use crate::error::CommonError;
+use crate::logging::flush_logs;
+use crate::packet_recorder::PacketRecorder;
+use crate::seen_flood_cache::{ForwardCounts, SeenFloodIds, SeenFragmentCache};
+use crate::topology::{RouteCache, TopologyGraph};
 use crate::{log_error, log_status};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ServerType {
     Content,
     Communication,
     Undefined,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl fmt::Display for ServerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ServerType::Content => "Content",
+            ServerType::Communication => "Communication",
+            ServerType::Undefined => "Undefined",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Error returned when parsing an unknown server type name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseServerTypeError(pub String);
+
+impl fmt::Display for ParseServerTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown server type: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseServerTypeError {}
+
+impl FromStr for ServerType {
+    type Err = ParseServerTypeError;
+    
+    /// Parses a server type from its `Display` name ("Content", "Communication" or "Undefined").
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Content" => Ok(ServerType::Content),
+            "Communication" => Ok(ServerType::Communication),
+            "Undefined" => Ok(ServerType::Undefined),
+            _ => Err(ParseServerTypeError(s.to_string())),
+        }
+    }
+}
+
+/// Application-level error sent by a server in `SerializableMessage::ErrorMessage`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AppError {
+    FileNotFound,
+    NotRegistered,
+    UnknownServerType,
+    RecipientUnreachable,
+    ServerBusy,
+    Other(String),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::FileNotFound => write!(f, "File not found"),
+            AppError::NotRegistered => write!(f, "Client not registered to the server"),
+            AppError::UnknownServerType => write!(f, "Unknown server type"),
+            AppError::RecipientUnreachable => write!(f, "Recipient unreachable"),
+            AppError::ServerBusy => write!(f, "Server busy"),
+            AppError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// A message exchanged by clients and servers, split into fragments to be sent.
+///
+/// Every variant only holds ids, strings, byte vectors and other hashable types, so messages
+/// can be compared and stored in sets, e.g. to skip the messages already seen. The commands
+/// cannot: `ClientCommand` and `ServerCommand` carry a `Sender`, and `SerializableDroneCommand`
+/// an `f32`, which only allows `PartialEq`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SerializableMessage {
     // For all the variants, the first argument is the sender
     Default,
@@ -35,7 +114,114 @@ pub enum SerializableMessage {
     ClientListRequest(NodeId),              // argument is the sender id (client)
     ClientListResponse(NodeId, Vec<NodeId>),// arguments are: the sender id (server) and the list of clients
     Chat(NodeId, NodeId, NodeId, String),   // arguments are: the sender id (client), the id of server the chat is sent on, the recipient client id and the chat text message
-    ErrorMessage(NodeId, String),           // argument are: the sender id (server) and the error message
+    ErrorMessage(NodeId, AppError),         // argument are: the sender id (server) and the error
+    // Ping and Pong are end-to-end liveness probes sent with source routing like any other message;
+    // they are unrelated to the flood mechanism used for topology discovery.
+    Ping(NodeId),                           // argument is the sender id (client)
+    Pong(NodeId),                           // argument is the sender id (server answering the Ping)
+    FileRequestRange(NodeId, String, u64, u64), // arguments are: the sender id (client), the name of the requested file, the start byte and the length
+    FileChunk(NodeId, String, u64, Vec<u8>),    // arguments are: the sender id (server), the filename, the start byte and the bytes of the requested range
+    FileMetadataRequest(NodeId, String),        // arguments are: the sender id (client) and the name of the file
+    FileMetadataResponse(NodeId, String, u64, String), // arguments are: the sender id (server), the filename, the size in bytes and the lowercase hex SHA-256 of the content (see `file_hash`)
+    FileUpload(NodeId, String, Vec<u8>),        // arguments are: the sender id (client), the filename and the content; a server over capacity answers ErrorMessage(AppError::ServerBusy)
+    UploadAck(NodeId, String),                  // arguments are: the sender id (server) and the name of the stored file
+    // Channels are additive to Chat: a client can still chat with a single client while being
+    // a member of channels. The communication server relays a ChannelMessage to every member.
+    JoinChannel(NodeId, String),                // arguments are: the sender id (client) and the channel name
+    LeaveChannel(NodeId, String),               // arguments are: the sender id (client) and the channel name
+    ChannelMessage(NodeId, NodeId, String, String), // arguments are: the sender id (client), the id of server the message is sent on, the channel name and the text message
+    // The server relays a BroadcastChat to every registered client except the sender, individually.
+    // Delivery is best-effort: each relayed message follows the usual routing rules.
+    BroadcastChat(NodeId, NodeId, String),      // arguments are: the sender id (client), the id of server the message is sent on and the text message
+}
+
+/// Returns the hash of a file's content as sent in `FileMetadataResponse`:
+/// the SHA-256 digest, as 64 lowercase hexadecimal characters.
+pub fn file_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+impl SerializableMessage {
+    /// Returns the id of the node that sent the message, i.e. its first argument.
+    ///
+    /// # Returns
+    ///
+    /// The sender id, or `None` for the `Default` variant which carries no data.
+    pub fn sender(&self) -> Option<NodeId> {
+        match self {
+            SerializableMessage::Default => None,
+            SerializableMessage::ServerTypeRequest(sender)
+            | SerializableMessage::ServerTypeResponse(sender, _)
+            | SerializableMessage::FileListRequest(sender)
+            | SerializableMessage::FileListResponse(sender, _)
+            | SerializableMessage::FileRequest(sender, _)
+            | SerializableMessage::FileFound(sender, _, _)
+            | SerializableMessage::RegisterToCommunicationServer(sender)
+            | SerializableMessage::RegisterSuccess(sender)
+            | SerializableMessage::ClientListRequest(sender)
+            | SerializableMessage::ClientListResponse(sender, _)
+            | SerializableMessage::Chat(sender, _, _, _)
+            | SerializableMessage::ErrorMessage(sender, _)
+            | SerializableMessage::Ping(sender)
+            | SerializableMessage::Pong(sender)
+            | SerializableMessage::FileRequestRange(sender, _, _, _)
+            | SerializableMessage::FileChunk(sender, _, _, _)
+            | SerializableMessage::FileMetadataRequest(sender, _)
+            | SerializableMessage::FileMetadataResponse(sender, _, _, _)
+            | SerializableMessage::FileUpload(sender, _, _)
+            | SerializableMessage::UploadAck(sender, _)
+            | SerializableMessage::JoinChannel(sender, _)
+            | SerializableMessage::LeaveChannel(sender, _)
+            | SerializableMessage::ChannelMessage(sender, _, _, _)
+            | SerializableMessage::BroadcastChat(sender, _, _) => Some(*sender),
+        }
+    }
+    
+    /// Builds a server's answer to a `FileMetadataRequest` for an existing file.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_id` - The id of the answering server.
+    /// * `filename` - The name of the requested file.
+    /// * `content` - The whole content of the file.
+    pub fn file_metadata_response(server_id: NodeId, filename: String, content: &[u8]) -> SerializableMessage {
+        SerializableMessage::FileMetadataResponse(server_id, filename, content.len() as u64, file_hash(content))
+    }
+    
+    /// Builds a server's answer to a `FileRequestRange`.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_id` - The id of the answering server.
+    /// * `filename` - The name of the requested file.
+    /// * `content` - The whole content of the file.
+    /// * `start` - The first requested byte.
+    /// * `length` - The number of requested bytes; the range is cut at the end of the file.
+    ///
+    /// # Returns
+    ///
+    /// A `FileChunk` with the requested slice, or an `ErrorMessage` if `start` is beyond the end of the file.
+    pub fn file_chunk_response(
+        server_id: NodeId,
+        filename: String,
+        content: &[u8],
+        start: u64,
+        length: u64,
+    ) -> SerializableMessage {
+        let file_size = content.len() as u64;
+        if start > file_size || (start == file_size && file_size > 0) {
+            return SerializableMessage::ErrorMessage(
+                server_id,
+                AppError::Other(format!("Range start {} is beyond the end of {}", start, filename)),
+            );
+        }
+        let end = start.saturating_add(length).min(file_size);
+        let chunk = content[start as usize..end as usize].to_vec();
+        SerializableMessage::FileChunk(server_id, filename, start, chunk)
+    }
 }
 
 impl Default for SerializableMessage {
@@ -45,6 +231,530 @@ impl Default for SerializableMessage {
     }
 }
 
+/// Builds a `SerializableMessage::Chat` with named setters, so that the three node ids of the
+/// variant cannot be swapped by mistake.
+///
+/// ```ignore
+/// let chat = ChatBuilder::new().from(client).via(server).to(recipient).text("Hello").build()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ChatBuilder {
+    client: Option<NodeId>,
+    server: Option<NodeId>,
+    recipient: Option<NodeId>,
+    text: Option<String>,
+}
+
+impl ChatBuilder {
+    /// Creates a builder with no field set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Sets the id of the client sending the chat message.
+    pub fn from(mut self, client: NodeId) -> Self {
+        self.client = Some(client);
+        self
+    }
+    
+    /// Sets the id of the communication server the chat message is sent on.
+    pub fn via(mut self, server: NodeId) -> Self {
+        self.server = Some(server);
+        self
+    }
+    
+    /// Sets the id of the client receiving the chat message.
+    pub fn to(mut self, recipient: NodeId) -> Self {
+        self.recipient = Some(recipient);
+        self
+    }
+    
+    /// Sets the text of the chat message.
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+    
+    /// Builds the chat message.
+    ///
+    /// # Returns
+    ///
+    /// The `SerializableMessage::Chat`, or `CommonError::MissingField` naming the first field
+    /// left unset (`from`, `via`, `to` or `text`).
+    pub fn build(self) -> Result<SerializableMessage, CommonError> {
+        Ok(SerializableMessage::Chat(
+            self.client.ok_or(CommonError::MissingField("from"))?,
+            self.server.ok_or(CommonError::MissingField("via"))?,
+            self.recipient.ok_or(CommonError::MissingField("to"))?,
+            self.text.ok_or(CommonError::MissingField("text"))?,
+        ))
+    }
+}
+
+impl fmt::Display for SerializableMessage {
+    /// Writes a one-line summary of the message for logging.
+    ///
+    /// File contents and byte payloads are summarized by their size instead of being written out.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializableMessage::Default => write!(f, "Default"),
+            SerializableMessage::ServerTypeRequest(_) => write!(f, "ServerTypeRequest"),
+            SerializableMessage::ServerTypeResponse(_, server_type) => {
+                write!(f, "ServerTypeResponse({})", server_type)
+            }
+            SerializableMessage::FileListRequest(_) => write!(f, "FileListRequest"),
+            SerializableMessage::FileListResponse(_, files) => {
+                write!(f, "FileListResponse({} files)", files.len())
+            }
+            SerializableMessage::FileRequest(_, filename) => write!(f, "FileRequest({})", filename),
+            SerializableMessage::FileFound(_, filename, file) => {
+                write!(f, "FileFound({}, {} bytes)", filename, file.len())
+            }
+            SerializableMessage::RegisterToCommunicationServer(_) => {
+                write!(f, "RegisterToCommunicationServer")
+            }
+            SerializableMessage::RegisterSuccess(_) => write!(f, "RegisterSuccess"),
+            SerializableMessage::ClientListRequest(_) => write!(f, "ClientListRequest"),
+            SerializableMessage::ClientListResponse(_, clients) => {
+                write!(f, "ClientListResponse({:?})", clients)
+            }
+            SerializableMessage::Chat(_, server_id, recipient_id, message) => write!(
+                f,
+                "Chat(to {} via {}, {} chars)",
+                recipient_id,
+                server_id,
+                message.chars().count()
+            ),
+            SerializableMessage::ErrorMessage(_, error) => write!(f, "ErrorMessage({})", error),
+            SerializableMessage::Ping(_) => write!(f, "Ping"),
+            SerializableMessage::Pong(_) => write!(f, "Pong"),
+            SerializableMessage::FileRequestRange(_, filename, start, length) => write!(
+                f,
+                "FileRequestRange({}, {}..{})",
+                filename,
+                start,
+                start.saturating_add(*length)
+            ),
+            SerializableMessage::FileChunk(_, filename, start, bytes) => {
+                write!(f, "FileChunk({}, {} bytes from {})", filename, bytes.len(), start)
+            }
+            SerializableMessage::FileMetadataRequest(_, filename) => {
+                write!(f, "FileMetadataRequest({})", filename)
+            }
+            SerializableMessage::FileMetadataResponse(_, filename, size, _) => {
+                write!(f, "FileMetadataResponse({}, {} bytes)", filename, size)
+            }
+            SerializableMessage::FileUpload(_, filename, content) => {
+                write!(f, "FileUpload({}, {} bytes)", filename, content.len())
+            }
+            SerializableMessage::UploadAck(_, filename) => write!(f, "UploadAck({})", filename),
+            SerializableMessage::JoinChannel(_, channel) => write!(f, "JoinChannel({})", channel),
+            SerializableMessage::LeaveChannel(_, channel) => write!(f, "LeaveChannel({})", channel),
+            SerializableMessage::ChannelMessage(_, server_id, channel, message) => write!(
+                f,
+                "ChannelMessage(to {} via {}, {} chars)",
+                channel,
+                server_id,
+                message.chars().count()
+            ),
+            SerializableMessage::BroadcastChat(_, server_id, message) => write!(
+                f,
+                "BroadcastChat(via {}, {} chars)",
+                server_id,
+                message.chars().count()
+            ),
+        }
+    }
+}
+
+/// Error returned when a `SerializableMessage` cannot be rebuilt from its fragments.
+#[derive(Debug)]
+pub enum DeserializeError {
+    /// No fragment was provided.
+    NoFragments,
+    /// Some fragments are missing or duplicated: `received` fragments for `expected` declared ones.
+    MissingFragments { expected: u64, received: usize },
+    /// The fragment with this index declares a length inconsistent with its position
+    /// (only the last fragment may carry less than `FRAGMENT_SIZE` bytes).
+    InvalidLength(u64),
+    /// The CRC32 of the reassembled message differs from the one computed by the sender.
+    ChecksumMismatch { expected: u32, computed: u32 },
+    /// The reassembled bytes are not a valid bincode-encoded message.
+    Decode(bincode::Error),
+    /// The reassembled bytes are not a valid JSON-encoded message.
+    JsonDecode(serde_json::Error),
+    /// The compression header byte of the message is unknown.
+    UnknownCompression(u8),
+    /// The compressed message could not be decompressed.
+    Decompress(std::io::Error),
+    /// The compressed message decompresses to more than `max` bytes.
+    DecompressedTooLarge { max: usize },
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeserializeError::NoFragments => write!(f, "no fragment to deserialize"),
+            DeserializeError::MissingFragments { expected, received } => {
+                write!(f, "expected {} fragments, received {}", expected, received)
+            }
+            DeserializeError::InvalidLength(index) => {
+                write!(f, "fragment {} has an invalid length", index)
+            }
+            DeserializeError::ChecksumMismatch { expected, computed } => write!(
+                f,
+                "checksum mismatch: expected {:#010x}, computed {:#010x}",
+                expected, computed
+            ),
+            DeserializeError::Decode(e) => write!(f, "failed to decode the message: {}", e),
+            DeserializeError::JsonDecode(e) => {
+                write!(f, "failed to decode the JSON message: {}", e)
+            }
+            DeserializeError::UnknownCompression(header) => {
+                write!(f, "unknown compression header {:#04x}", header)
+            }
+            DeserializeError::Decompress(e) => write!(f, "failed to decompress the message: {}", e),
+            DeserializeError::DecompressedTooLarge { max } => {
+                write!(f, "the decompressed message is larger than {} bytes", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+/// Returns the options of the bincode encoding of `SerializableMessage`:
+/// variable-length integers, rejecting trailing bytes.
+fn wire_encoding() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+/// Encoding of `SerializableMessage` on the wire.
+///
+/// Both ends of a conversation must use the same encoding. Bincode is the default since
+/// it produces fewer fragments; JSON is human-readable, which helps when debugging.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Bincode,
+    Json,
+}
+
+/// Compression of an encoded message, see `SerializableMessage::into_compressed_fragments`.
+///
+/// The whole encoded message is compressed, whatever its variant. Compression pays off for
+/// file transfers (`FileFound`, `FileUpload`) of compressible content; small messages are
+/// better sent with `None`, which only costs the header byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the header byte marking the compression of a message.
+    fn header(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+        }
+    }
+}
+
+/// Compression level used for `Compression::Zstd`, zstd's default trade-off.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Decompresses a `Compression::Zstd` message.
+///
+/// The output buffer grows with the decompressed data instead of being allocated for
+/// `MAX_MESSAGE_BYTES` up front. Returns a `DeserializeError` if the data is not valid zstd
+/// or decompresses to more than `MAX_MESSAGE_BYTES` bytes.
+fn decompress_message(payload: &[u8]) -> Result<Vec<u8>, DeserializeError> {
+    use std::io::Read;
+    
+    let decoder = zstd::stream::Decoder::new(payload).map_err(DeserializeError::Decompress)?;
+    let mut decompressed = Vec::new();
+    decoder
+        .take(MAX_MESSAGE_BYTES as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(DeserializeError::Decompress)?;
+    if decompressed.len() > MAX_MESSAGE_BYTES {
+        return Err(DeserializeError::DecompressedTooLarge { max: MAX_MESSAGE_BYTES });
+    }
+    Ok(decompressed)
+}
+
+/// Concatenates the data of all the fragments of a message of `FRAGMENT_SIZE`-byte fragments.
+///
+/// Returns a `DeserializeError` if fragments are missing or their declared lengths are inconsistent.
+fn reassemble_fragments(fragments: &[Fragment]) -> Result<Vec<u8>, DeserializeError> {
+    reassemble_fragments_with_size(fragments, FRAGMENT_SIZE)
+}
+
+/// Concatenates the data of all the fragments of a message, given in any order.
+///
+/// Counterpart of `fragment_message_with_size`: the `length` of each fragment gives the number
+/// of meaningful bytes, which must be `fragment_size` for every fragment but the last.
+///
+/// # Arguments
+///
+/// * `fragments` - Every fragment of the message.
+/// * `fragment_size` - The number of data bytes the message was split into per fragment.
+///
+/// # Returns
+///
+/// The message bytes, or a `DeserializeError` if fragments are missing or their declared
+/// lengths are inconsistent.
+pub fn reassemble_fragments_with_size(
+    fragments: &[Fragment],
+    fragment_size: usize,
+) -> Result<Vec<u8>, DeserializeError> {
+    let expected = fragments
+        .first()
+        .ok_or(DeserializeError::NoFragments)?
+        .total_n_fragments;
+    
+    let mut ordered: Vec<&Fragment> = fragments.iter().collect();
+    ordered.sort_by_key(|fragment| fragment.fragment_index);
+    let is_complete = ordered.len() as u64 == expected
+        && ordered.iter().enumerate().all(|(index, fragment)| {
+            fragment.fragment_index == index as u64 && fragment.total_n_fragments == expected
+        });
+    if !is_complete {
+        return Err(DeserializeError::MissingFragments {
+            expected,
+            received: fragments.len(),
+        });
+    }
+    
+    let mut data = Vec::with_capacity(ordered.len() * fragment_size);
+    for fragment in &ordered {
+        let length = fragment.length as usize;
+        let is_last = fragment.fragment_index + 1 == expected;
+        if length > fragment_size.min(FRAGMENT_SIZE) || (!is_last && length != fragment_size) {
+            return Err(DeserializeError::InvalidLength(fragment.fragment_index));
+        }
+        data.extend_from_slice(&fragment.data[..length]);
+    }
+    Ok(data)
+}
+
+impl SerializableMessage {
+    /// Serializes the message with the given encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The wire encoding to use.
+    pub fn encode(&self, encoding: Encoding) -> Vec<u8> {
+        match encoding {
+            Encoding::Bincode => wire_encoding()
+                .serialize(self)
+                .expect("Failed to serialize the message"),
+            Encoding::Json => serde_json::to_vec(self).expect("Failed to serialize the message"),
+        }
+    }
+    
+    /// Deserializes a message encoded with `encode`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded message.
+    /// * `encoding` - The wire encoding the message was encoded with.
+    ///
+    /// # Returns
+    ///
+    /// The decoded message, or `DeserializeError::Decode` / `DeserializeError::JsonDecode`
+    /// if the bytes are not a valid message in that encoding.
+    pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<Self, DeserializeError> {
+        match encoding {
+            Encoding::Bincode => wire_encoding()
+                .deserialize(bytes)
+                .map_err(DeserializeError::Decode),
+            Encoding::Json => serde_json::from_slice(bytes).map_err(DeserializeError::JsonDecode),
+        }
+    }
+    
+    /// Serializes the message and splits it into `MsgFragment` packets.
+    ///
+    /// As with `fragment_message`, the packets have an empty routing header.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session id given to every packet.
+    /// * `encoding` - The wire encoding to use.
+    ///
+    /// # Returns
+    ///
+    /// The fragment packets, or `CommonError::MessageTooLarge` if the encoded message
+    /// is longer than `MAX_MESSAGE_BYTES`.
+    pub fn into_fragments(self, session_id: u64, encoding: Encoding) -> Result<Vec<Packet>, CommonError> {
+        self.into_fragments_with_limit(session_id, encoding, MAX_MESSAGE_BYTES)
+    }
+    
+    /// Same as `into_fragments`, with a custom maximum size for the encoded message.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session id given to every packet.
+    /// * `encoding` - The wire encoding to use.
+    /// * `max_bytes` - The maximum number of bytes of the encoded message.
+    pub fn into_fragments_with_limit(
+        self,
+        session_id: u64,
+        encoding: Encoding,
+        max_bytes: usize,
+    ) -> Result<Vec<Packet>, CommonError> {
+        fragment_message_with_limit(&self.encode(encoding), session_id, max_bytes)
+    }
+    
+    /// Rebuilds a message from all of its fragments, given in any order.
+    ///
+    /// # Arguments
+    ///
+    /// * `fragments` - Every fragment of the message.
+    /// * `encoding` - The wire encoding the message was sent with.
+    ///
+    /// # Returns
+    ///
+    /// The decoded message, or `CommonError::DeserializeFailed` if fragments are missing,
+    /// their declared lengths are inconsistent, or the reassembled bytes are not a valid message.
+    pub fn from_fragments(fragments: &[Fragment], encoding: Encoding) -> Result<Self, CommonError> {
+        let data = reassemble_fragments(fragments)?;
+        Ok(Self::decode(&data, encoding)?)
+    }
+    
+    /// Serializes the message, optionally compresses it and splits it into `MsgFragment` packets.
+    ///
+    /// The first byte of the fragmented data marks whether the encoded message was compressed,
+    /// so the receiver does not need to know it in advance: use `from_compressed_fragments`.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session id given to every packet.
+    /// * `encoding` - The wire encoding to use.
+    /// * `compression` - The compression applied to the encoded message.
+    ///
+    /// # Returns
+    ///
+    /// The fragment packets, or `CommonError::MessageTooLarge` if the compressed data
+    /// is longer than `MAX_MESSAGE_BYTES`.
+    pub fn into_compressed_fragments(
+        self,
+        session_id: u64,
+        encoding: Encoding,
+        compression: Compression,
+    ) -> Result<Vec<Packet>, CommonError> {
+        let encoded = self.encode(encoding);
+        let mut data = vec![compression.header()];
+        match compression {
+            Compression::None => data.extend_from_slice(&encoded),
+            Compression::Zstd => data.extend(
+                zstd::bulk::compress(&encoded, ZSTD_LEVEL).expect("Failed to compress the message"),
+            ),
+        }
+        fragment_message_with_limit(&data, session_id, MAX_MESSAGE_BYTES)
+    }
+    
+    /// Rebuilds a message sent with `into_compressed_fragments` from all of its fragments.
+    ///
+    /// # Arguments
+    ///
+    /// * `fragments` - Every fragment of the message.
+    /// * `encoding` - The wire encoding the message was sent with.
+    ///
+    /// # Returns
+    ///
+    /// The decoded message, or `CommonError::DeserializeFailed` if the message cannot be
+    /// reassembled, decompressed (at most `MAX_MESSAGE_BYTES` once decompressed) or decoded.
+    pub fn from_compressed_fragments(
+        fragments: &[Fragment],
+        encoding: Encoding,
+    ) -> Result<Self, CommonError> {
+        let data = reassemble_fragments(fragments)?;
+        let (header, payload) = data.split_first().ok_or(DeserializeError::InvalidLength(0))?;
+        let encoded = match *header {
+            0 => payload.to_vec(),
+            1 => decompress_message(payload)?,
+            other => return Err(DeserializeError::UnknownCompression(other).into()),
+        };
+        Ok(Self::decode(&encoded, encoding)?)
+    }
+}
+
+/// A `SerializableMessage` sent along with a CRC32 of its encoding.
+///
+/// Sending a message through `Checksummed` instead of `SerializableMessage::into_fragments`
+/// lets the receiver detect a corrupted or wrongly reassembled message: the 4-byte
+/// little-endian CRC32 precedes the encoded message in the fragments.
+#[derive(Debug, Clone)]
+pub struct Checksummed(pub SerializableMessage);
+
+impl Checksummed {
+    /// Serializes the message, prefixes it with its CRC32 and splits it into `MsgFragment` packets.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session id given to every packet.
+    /// * `encoding` - The wire encoding to use.
+    ///
+    /// # Returns
+    ///
+    /// The fragment packets, or `CommonError::MessageTooLarge` if the checksummed encoding
+    /// is longer than `MAX_MESSAGE_BYTES`.
+    pub fn into_fragments(self, session_id: u64, encoding: Encoding) -> Result<Vec<Packet>, CommonError> {
+        self.into_fragments_with_limit(session_id, encoding, MAX_MESSAGE_BYTES)
+    }
+    
+    /// Same as `into_fragments`, with a custom maximum size for the checksummed encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session id given to every packet.
+    /// * `encoding` - The wire encoding to use.
+    /// * `max_bytes` - The maximum number of bytes of the checksum and encoded message together.
+    pub fn into_fragments_with_limit(
+        self,
+        session_id: u64,
+        encoding: Encoding,
+        max_bytes: usize,
+    ) -> Result<Vec<Packet>, CommonError> {
+        let encoded = self.0.encode(encoding);
+        let mut data = crc32fast::hash(&encoded).to_le_bytes().to_vec();
+        data.extend_from_slice(&encoded);
+        fragment_message_with_limit(&data, session_id, max_bytes)
+    }
+    
+    /// Rebuilds a message from all of its fragments, given in any order, and verifies its CRC32.
+    ///
+    /// # Arguments
+    ///
+    /// * `fragments` - Every fragment of the message.
+    /// * `encoding` - The wire encoding the message was sent with.
+    ///
+    /// # Returns
+    ///
+    /// The decoded message, or `CommonError::DeserializeFailed`, notably with
+    /// `DeserializeError::ChecksumMismatch` if the message was corrupted.
+    pub fn from_fragments(
+        fragments: &[Fragment],
+        encoding: Encoding,
+    ) -> Result<SerializableMessage, CommonError> {
+        let data = reassemble_fragments(fragments)?;
+        if data.len() < 4 {
+            return Err(DeserializeError::InvalidLength(0).into());
+        }
+        let (checksum, encoded) = data.split_at(4);
+        let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        let computed = crc32fast::hash(encoded);
+        if expected != computed {
+            return Err(DeserializeError::ChecksumMismatch { expected, computed }.into());
+        }
+        Ok(SerializableMessage::decode(encoded, encoding)?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub enum ClientCommand {
     ServerTypeRequest(NodeId),             // argument is the id of the server we want to get the type of
     AllServerTypesRequest(),
@@ -55,11 +765,14 @@ pub enum ClientCommand {
     ClientListRequest(NodeId),             // argument is the id of the server we want to get the client list from
     SendPacket(Packet),
     RemoveSender(NodeId),
+    #[serde(skip)] // a channel cannot be sent over the wire
     AddSender(NodeId, Sender<Packet>),
 }
 
+#[derive(Serialize, Deserialize)]
 pub enum ServerCommand {
     RemoveSender(NodeId),
+    #[serde(skip)] // a channel cannot be sent over the wire
     AddSender(NodeId, Sender<Packet>),
     SetServerType(ServerType),
 }
@@ -68,6 +781,167 @@ pub enum Command {
     Client(ClientCommand),
     Server(ServerCommand),
     Drone(DroneCommand),
+    // A command whose outcome is reported back, see `NetworkNode::dispatch_command`
+    Tracked(u64, Box<Command>, Sender<CommandResult>), // arguments are: the command id chosen by the controller, the command and the channel receiving the result
+    DumpState(Sender<NodeStateSnapshot>), // argument is the channel receiving the snapshot of the node's state
+    Heartbeat, // asks the node to emit a heartbeat right away, see `NetworkNode::get_heartbeat_emitter`
+    Shutdown, // stops the node, see `NetworkNode::is_shutting_down` and `NetworkNode::shut_down`
+}
+
+/// The `DroneCommand`s that can be serialized, i.e. all of them but `AddSender`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SerializableDroneCommand {
+    RemoveSender(NodeId),
+    SetPacketDropRate(f32),
+    Crash,
+}
+
+/// The commands that can be sent over a socket or recorded, to drive a simulation remotely.
+///
+/// Commands carrying a channel (`Command::Tracked`, `Command::DumpState` and the
+/// `AddSender` commands) have no serializable counterpart, since the channel only exists in
+/// the process that created it. Serializing a `ClientCommand::AddSender` or a
+/// `ServerCommand::AddSender` fails.
+#[derive(Serialize, Deserialize)]
+pub enum SerializableCommand {
+    Client(ClientCommand),
+    Server(ServerCommand),
+    Drone(SerializableDroneCommand),
+    Heartbeat,
+    Shutdown,
+}
+
+impl From<SerializableDroneCommand> for DroneCommand {
+    fn from(command: SerializableDroneCommand) -> Self {
+        match command {
+            SerializableDroneCommand::RemoveSender(node_id) => DroneCommand::RemoveSender(node_id),
+            SerializableDroneCommand::SetPacketDropRate(pdr) => DroneCommand::SetPacketDropRate(pdr),
+            SerializableDroneCommand::Crash => DroneCommand::Crash,
+        }
+    }
+}
+
+impl From<SerializableCommand> for Command {
+    fn from(command: SerializableCommand) -> Self {
+        match command {
+            SerializableCommand::Client(command) => Command::Client(command),
+            SerializableCommand::Server(command) => Command::Server(command),
+            SerializableCommand::Drone(command) => Command::Drone(command.into()),
+            SerializableCommand::Heartbeat => Command::Heartbeat,
+            SerializableCommand::Shutdown => Command::Shutdown,
+        }
+    }
+}
+
+impl TryFrom<Command> for SerializableCommand {
+    /// The command itself, when it carries a channel.
+    type Error = Command;
+    
+    fn try_from(command: Command) -> Result<Self, Self::Error> {
+        match command {
+            Command::Client(ClientCommand::AddSender(..))
+            | Command::Server(ServerCommand::AddSender(..))
+            | Command::Drone(DroneCommand::AddSender(..))
+            | Command::Tracked(..)
+            | Command::DumpState(_) => Err(command),
+            Command::Client(command) => Ok(SerializableCommand::Client(command)),
+            Command::Server(command) => Ok(SerializableCommand::Server(command)),
+            Command::Drone(DroneCommand::RemoveSender(node_id)) => {
+                Ok(SerializableCommand::Drone(SerializableDroneCommand::RemoveSender(node_id)))
+            }
+            Command::Drone(DroneCommand::SetPacketDropRate(pdr)) => {
+                Ok(SerializableCommand::Drone(SerializableDroneCommand::SetPacketDropRate(pdr)))
+            }
+            Command::Drone(DroneCommand::Crash) => Ok(SerializableCommand::Drone(SerializableDroneCommand::Crash)),
+            Command::Heartbeat => Ok(SerializableCommand::Heartbeat),
+            Command::Shutdown => Ok(SerializableCommand::Shutdown),
+        }
+    }
+}
+
+/// Outcome of a `Command::Tracked`, sent back to the controller that issued it.
+#[derive(Debug)]
+pub struct CommandResult {
+    /// The id given to the command by the controller.
+    pub command_id: u64,
+    /// Whether the node managed to execute the command.
+    pub outcome: Result<(), CommonError>,
+}
+
+/// Counters describing the traffic handled by a network node.
+///
+/// They are updated by the default implementations of the `NetworkNode` methods
+/// and can be snapshotted by the simulation controller through `get_metrics`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeMetrics {
+    /// Packets successfully sent to a neighbour, including broadcast flood requests.
+    pub forwarded: u64,
+    /// Packets dropped by the node.
+    pub dropped: u64,
+    /// Flood requests received.
+    pub flood_requests_seen: u64,
+    /// Flood responses built from received flood requests.
+    pub flood_responses_built: u64,
+    /// Nacks built in response to received packets.
+    pub nacks_built: u64,
+}
+
+/// State of a node reported in answer to `Command::DumpState`, for debugging.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeStateSnapshot {
+    /// The id of the node.
+    pub id: NodeId,
+    /// The ids of the node's neighbours, sorted.
+    pub neighbors: Vec<NodeId>,
+    /// The number of flood ids the node remembers having seen.
+    pub seen_flood_count: usize,
+    /// The traffic counters of the node.
+    pub metrics: NodeMetrics,
+}
+
+/// Why a node dropped a packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropReason {
+    /// The node is crashing.
+    Crashed,
+    /// The packet was randomly dropped according to the node's packet drop rate.
+    RandomDrop,
+    /// The packet is malformed, e.g. a flood request with an empty path trace.
+    Malformed,
+    /// The node has no channel towards the next hop of the packet.
+    NoRoute,
+    /// The node is not the current hop of the packet, see `NetworkNode::nack_unexpected_recipient`.
+    UnexpectedRecipient,
+    /// The node has forwarded too many packets of the session, see `NetworkNode::get_max_forwards`.
+    ForwardLimit,
+}
+
+/// A packet dropped by a node, with the reason of the drop.
+///
+/// `DroneEvent::PacketDropped` does not say why a packet was dropped: nodes returning a sender
+/// from `NetworkNode::get_drop_record_send` also send a `DropRecord` to the simulation
+/// controller, e.g. to build a histogram of the drops per reason.
+#[derive(Clone, Debug)]
+pub struct DropRecord {
+    /// The id of the node that dropped the packet.
+    pub node_id: NodeId,
+    /// Why the packet was dropped.
+    pub reason: DropReason,
+    /// The dropped packet.
+    pub packet: Packet,
+}
+
+/// What happened during one iteration of `NetworkNode::run_once`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// A packet or a command was received and handled.
+    Handled,
+    /// Nothing was received before the timeout.
+    TimedOut,
+    /// The packet or the command channel is disconnected: no more work will arrive on it.
+    Disconnected,
+    /// A `Command::Shutdown` was received.
+    Shutdown,
 }
 
 /// Common network functionality shared across different node types.
@@ -77,29 +951,84 @@ pub trait NetworkNode {
     /// Retrieves the unique identifier of this network node.
     fn get_id(&self) -> NodeId;
     
+    /// Returns the type of this network node, as recorded in flood request path traces.
+    fn get_node_type(&self) -> NodeType;
+    
     /// Indicates whether the node is set to exhibit crashing behavior.
     /// The default implementation returns `false`.
     fn get_crashing_behavior(&self) -> bool {
         return false;
     }
     
-    /// Provides a mutable reference to the set of flood request IDs that have already been seen.
+    /// Returns the probability, between 0 and 1, that the node drops a message fragment.
+    /// The default implementation returns `0.0`, i.e. the node never drops fragments.
+    fn get_pdr(&self) -> f32 {
+        0.0
+    }
+    
+    /// Indicates whether the node received a `Command::Shutdown`.
+    ///
+    /// Nodes set a flag when `handle_command` receives `Command::Shutdown` and return it here;
+    /// `run` stops as soon as it is set. The default implementation returns `false`.
+    fn is_shutting_down(&self) -> bool {
+        false
+    }
+    
+    /// Indicates whether the packets still queued when the node shuts down must be answered.
+    ///
+    /// If `true`, `shut_down` empties the packet receiver as `crash` does, Nacking the pending
+    /// message fragments. The default implementation returns `false`: pending packets are lost.
+    fn nack_pending_on_shutdown(&self) -> bool {
+        false
+    }
+    
+    /// Provides a mutable reference to the set of flood requests that have already been seen.
     /// This helps to avoid reprocessing duplicate flood requests.
-    fn get_seen_flood_ids(&mut self) -> &mut HashSet<String>;
+    ///
+    /// Each flood request is identified by its `(initiator_id, flood_id)` pair.
+    /// Implementers migrating from the former `HashSet<String>` of `"<initiator>_<flood>"`
+    /// keys only need to change the type of their field to `HashSet<(NodeId, u64)>`.
+    /// To bound memory usage, the field can instead be a `SeenFloodCache`.
+    fn get_seen_flood_ids(&mut self) -> &mut dyn SeenFloodIds;
     
     /// Returns a mutable reference to the mapping of node IDs to their sender channels.
     /// This map represents the outgoing communication channels for this node.
     fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>>;
     
+    /// Returns a reference to the mapping of node IDs to their sender channels.
+    ///
+    /// Read-only counterpart of `get_packet_send`, used wherever the channels are not
+    /// added or removed, so that it does not conflict with other borrows of the node.
+    fn packet_send_ref(&self) -> &HashMap<NodeId, Sender<Packet>>;
+    
     /// Returns a reference to the channel used for receiving incoming packets.
     fn get_packet_receiver(&self) -> &Receiver<Packet>;
     
+    /// Returns a reference to the channel used for receiving commands, if the node has one.
+    ///
+    /// Needed by `run_once` to service commands. The default implementation returns `None`,
+    /// in which case `run_once` only receives packets.
+    fn get_command_receiver(&self) -> Option<&Receiver<Command>> {
+        None
+    }
+    
     /// Returns a mutable reference to the node's random number generator.
-    fn get_random_generator(&mut self) -> &mut StdRng;
+    ///
+    /// Any generator can be used, e.g. a `StdRng` seeded per test for reproducible runs.
+    /// `rand::Rng` methods such as `gen()` are available on the returned generator.
+    fn get_random_generator(&mut self) -> &mut dyn RngCore;
     
     /// Returns a reference to the simulation controller's sender channel for dispatching events.
     fn get_sim_contr_send(&self) -> &Sender<DroneEvent>;
     
+    /// Returns a mutable reference to the node's traffic counters.
+    fn get_metrics(&mut self) -> &mut NodeMetrics;
+    
+    /// Returns a mutable reference to the last flood id generated by `next_flood_id`.
+    ///
+    /// The counter starts at 0, before any flood is initiated, and must not be modified otherwise.
+    fn get_last_flood_id(&mut self) -> &mut u64;
+    
     /// Processes a routed packet arriving at this node.
     ///
     /// # Arguments
@@ -118,62 +1047,396 @@ pub trait NetworkNode {
     /// * `command` - The command to be executed.
     fn handle_command(&mut self, command: Command);
     
-    /// Determines how to process an incoming packet based on its type and the node type.
+    /// Executes a command and reports whether it succeeded.
     ///
-    /// For flood requests, it may trigger a flood response or broadcast the request further.
-    /// For all other packets, it delegates processing to `handle_routed_packet`.
+    /// Used for `Command::Tracked`. The default implementation delegates to `handle_command`
+    /// and always succeeds: nodes override it to report failures such as a missing route.
     ///
     /// # Arguments
     ///
-    /// * `packet` - The packet to be handled.
-    /// * `node_type` - The type of the node handling the packet.
+    /// * `command` - The command to be executed.
     ///
     /// # Returns
     ///
-    /// A boolean status resulting from the packet handling.
-    fn handle_packet(&mut self, packet: Packet, node_type: NodeType) -> bool {
-        match packet.pack_type {
-            PacketType::FloodRequest(_) => {
-                if self.get_crashing_behavior() {
-                    true;
-                }
-                self.handle_flood_request(packet, node_type);
+    /// `Ok(())` if the command was executed, or the error that prevented it.
+    fn try_handle_command(&mut self, command: Command) -> Result<(), CommonError> {
+        self.handle_command(command);
+        Ok(())
+    }
+    
+    /// Builds a snapshot of the node's neighbours, seen floods and metrics.
+    fn state_snapshot(&mut self) -> NodeStateSnapshot {
+        let mut neighbors: Vec<NodeId> = self.packet_send_ref().keys().copied().collect();
+        neighbors.sort_unstable();
+        NodeStateSnapshot {
+            id: self.get_id(),
+            neighbors,
+            seen_flood_count: self.get_seen_flood_ids().len(),
+            metrics: *self.get_metrics(),
+        }
+    }
+    
+    /// Handles a command received from the simulation controller.
+    ///
+    /// A `Command::Tracked` is executed through `try_handle_command` and its outcome is sent
+    /// on the channel carried by the command; if the controller dropped the receiver, the
+    /// result is discarded. A `Command::DumpState` is answered with `state_snapshot`, and a
+    /// `Command::Heartbeat` with a heartbeat if the node has a `HeartbeatEmitter`.
+    /// A `Command::Shutdown` is passed to `handle_command`, then the node is torn down with
+    /// `shut_down`.
+    /// Every other command is passed to `handle_command`.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The received command.
+    fn dispatch_command(&mut self, command: Command) {
+        match command {
+            Command::Shutdown => {
+                self.handle_command(Command::Shutdown);
+                self.shut_down();
+            }
+            Command::Heartbeat => {
+                let id = self.get_id();
+                if let Some(emitter) = self.get_heartbeat_emitter() {
+                    emitter.beat(id);
+                }
+            }
+            Command::DumpState(snapshot_send) => {
+                let snapshot = self.state_snapshot();
+                if snapshot_send.send(snapshot).is_err() {
+                    log_error!(self.get_id(), "Failed to send the state snapshot");
+                }
+            }
+            Command::Tracked(command_id, command, result_send) => {
+                let outcome = self.try_handle_command(*command);
+                if result_send.send(CommandResult { command_id, outcome }).is_err() {
+                    log_error!(self.get_id(), "Failed to report the result of command {}", command_id);
+                }
+            }
+            command => self.handle_command(command),
+        }
+    }
+    
+    /// Runs one iteration of the node's event loop.
+    ///
+    /// Waits up to `timeout` for a packet on `get_packet_receiver` or a command on
+    /// `get_command_receiver`, and passes what arrives first to `handle_packet` or
+    /// `dispatch_command`. Components call this in a loop, doing their periodic maintenance
+    /// (e.g. `heartbeat_if_due`) between iterations.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait for a packet or a command.
+    ///
+    /// # Returns
+    ///
+    /// Whether something was handled, the timeout elapsed, a channel is disconnected or a
+    /// `Command::Shutdown` was received.
+    fn run_once(&mut self, timeout: Duration) -> RunOutcome {
+        let received = receive_next(self, Some(timeout));
+        handle_received(self, received)
+    }
+    
+    /// Tears the node down after a `Command::Shutdown`.
+    ///
+    /// Flushes the buffered log files and, if `nack_pending_on_shutdown` is set, answers the
+    /// packets still queued as `crash` does.
+    fn shut_down(&mut self) {
+        log_status!(self.get_id(), "Shutting down");
+        if self.nack_pending_on_shutdown() {
+            self.crash();
+        }
+        flush_logs();
+    }
+    
+    /// Runs the event loop of the node until it is told to stop.
+    ///
+    /// Waits on the packet and command channels together, passing packets to `handle_packet` and
+    /// commands to `dispatch_command`, until a `Command::Shutdown` is received, `is_shutting_down`
+    /// returns `true` or one of the channels is disconnected. Nodes without periodic maintenance can use this instead of
+    /// their own select loop; the others call `run_once` in a loop.
+    fn run(&mut self) {
+        loop {
+            let received = receive_next(self, None);
+            match handle_received(self, received) {
+                RunOutcome::Shutdown | RunOutcome::Disconnected => break,
+                RunOutcome::Handled | RunOutcome::TimedOut if self.is_shutting_down() => break,
+                RunOutcome::Handled | RunOutcome::TimedOut => {}
+            }
+        }
+    }
+    
+    /// Determines how to process an incoming packet based on its type and the node type.
+    ///
+    /// For flood requests, it may trigger a flood response or broadcast the request further.
+    /// A node with crashing behavior drops flood requests instead: they are neither
+    /// answered nor broadcast.
+    /// Flood responses are passed to `handle_flood_response`, once recorded by the flood tracker
+    /// and the route cache of the node if it has them.
+    /// For all other packets, it delegates processing to `handle_routed_packet`.
+    /// The packet is first recorded if the node has a `PacketRecorder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to be handled.
+    ///
+    /// # Returns
+    ///
+    /// A boolean status resulting from the packet handling. Flood requests, whether handled
+    /// or dropped, always yield `false`.
+    fn handle_packet(&mut self, packet: Packet) -> bool {
+        let id = self.get_id();
+        if let Some(recorder) = self.get_packet_recorder() {
+            if let Err(e) = recorder.record(&packet) {
+                log_error!(id, "Failed to record the packet: {:?}", e);
+            }
+        }
+        match packet.pack_type {
+            PacketType::FloodRequest(_) => {
+                if self.get_crashing_behavior() {
+                    log_status!(self.get_id(), "Crashing: dropping flood request");
+                    self.drop_packet(packet, DropReason::Crashed);
+                    return false;
+                }
+                self.handle_flood_request(packet);
                 false
             }
+            PacketType::FloodResponse(ref flood_response) => {
+                if packet.routing_header.hops.last() == Some(&id) {
+                    if let Some(tracker) = self.get_flood_tracker() {
+                        tracker.record(flood_response);
+                    }
+                    self.cache_return_route(&packet.routing_header);
+                }
+                let flood_response = flood_response.clone();
+                self.handle_flood_response(flood_response, packet)
+            }
+            PacketType::Ack(_) => {
+                if packet.routing_header.hops.last() == Some(&id) {
+                    self.cache_return_route(&packet.routing_header);
+                }
+                self.handle_routed_packet(packet)
+            }
             _ => self.handle_routed_packet(packet),
         }
     }
     
-    /// Forwards a packet to the next hop specified in the routing header.
+    /// Processes a flood response arriving at this node.
     ///
-    /// Before forwarding, a simulation event is sent. If the sender channel for the next hop
-    /// is not found, the event is logged.
+    /// The default implementation consumes the responses addressed to this node, which
+    /// `handle_packet` has already fed to the flood tracker and the route cache, and forwards
+    /// the others along their route. Clients and servers override it to add the path trace to
+    /// their `TopologyGraph`.
     ///
     /// # Arguments
     ///
-    /// * `packet` - The packet to be forwarded.
+    /// * `resp` - The received flood response.
+    /// * `packet` - The packet carrying the flood response.
     ///
-    /// # Panics
+    /// # Returns
     ///
-    /// Panics if sending the packet fails.
-    fn forward_packet(&mut self, packet: Packet) {
-        let next_hop_id = packet.routing_header.hops[packet.routing_header.hop_index];
-        
-        if let Some(sender) = self.get_packet_send().clone().get(&next_hop_id) {
-            // Send PacketSent event before forwarding
-            if let Err(e) = self
-                .get_sim_contr_send()
-                .send(DroneEvent::PacketSent(packet.clone()))
-            {
-                log_error!(self.get_id(), "Failed to send PacketSent event: {:?}", e);
+    /// `false` if the response could not be forwarded.
+    fn handle_flood_response(&mut self, resp: FloodResponse, packet: Packet) -> bool {
+        if packet.routing_header.hops.last() == Some(&self.get_id()) {
+            log_status!(
+                self.get_id(),
+                "Received the flood response to flood {} with path trace {:?}",
+                resp.flood_id,
+                resp.path_trace
+            );
+            return true;
+        }
+        // A flood response cannot be answered with a Nack: a misrouted one is dropped
+        if let Err(nack) = self.check_recipient(&packet) {
+            log_error!(self.get_id(), "Dropping a misrouted flood response: {:?}", nack.nack_type);
+            return false;
+        }
+        match self.forward_packet(packet) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!(self.get_id(), "Failed to forward the flood response: {:?}", e);
+                false
             }
-            sender.send(packet).expect("Failed to forward the packet");
+        }
+    }
+    
+    /// Sends an event to the simulation controller.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to send.
+    ///
+    /// # Returns
+    ///
+    /// `CommonError::ControllerUnreachable` if the channel towards the controller is closed.
+    fn emit_event(&self, event: DroneEvent) -> Result<(), CommonError> {
+        self.get_sim_contr_send().send(event).map_err(|e| {
+            log_error!(self.get_id(), "Failed to send {:?} to the simulation controller", e.0);
+            CommonError::ControllerUnreachable
+        })
+    }
+    
+    /// Forwards a packet to the next hop specified in the routing header.
+    ///
+    /// The routing header must point at this node, i.e. `hops[hop_index]` is this node's id,
+    /// as in a received packet or a header built with `build_routing_header`. The hop index is
+    /// incremented before sending, so that the next hop finds itself at `hops[hop_index]`.
+    ///
+    /// The route is checked with `validate_route` first, so that a looping packet is not sent.
+    /// Before forwarding, a `PacketSent` event is sent to the simulation controller. If the
+    /// controller is unreachable, the packet is not forwarded. If the sender channel for the next
+    /// hop is not found, the error is logged and returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to be forwarded, its header pointing at this node.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the packet was sent, otherwise `CommonError::NextHopUnreachable` or
+    /// `CommonError::ChannelClosed`, so the caller can send a Nack or reroute,
+    /// `CommonError::InvalidRoute` or `CommonError::EmptyRoute`, so the caller can send an
+    /// `ErrorInRouting` Nack, or
+    /// `CommonError::RouteExhausted` if this node is the last hop of the route or the hop index
+    /// is already past its end, or `CommonError::ControllerUnreachable`, so the node can halt.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                node_id = self.get_id(),
+                session_id = packet.session_id,
+                packet_type = packet_type_name(&packet.pack_type),
+            )
+        )
+    )]
+    fn forward_packet(&mut self, mut packet: Packet) -> Result<(), CommonError> {
+        let next_hop_id = self.prepare_forward(&mut packet)?;
+        if let Some(sender) = self.packet_send_ref().get(&next_hop_id) {
+            send_to_next_hop(self.get_id(), self.get_sim_contr_send(), next_hop_id, sender, packet)?;
+            self.get_metrics().forwarded += 1;
+            Ok(())
         } else {
             log_status!(
                 self.get_id(),
                 "No channel found for next hop: {:?}",
                 next_hop_id
             );
+            Err(CommonError::NextHopUnreachable(next_hop_id))
+        }
+    }
+    
+    /// Checks that a packet can be forwarded, and advances its routing header to the next hop.
+    ///
+    /// This is the part of `forward_packet` that precedes the send: the route is validated, a
+    /// packet over the forward limit of its session is dropped (see `get_max_forwards`), and a
+    /// blocked next hop is refused (see `set_broadcast_blocklist`).
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to forward, whose `hop_index` is incremented on success.
+    ///
+    /// # Returns
+    ///
+    /// The id of the next hop, or the error `forward_packet` returns without sending.
+    fn prepare_forward(&mut self, packet: &mut Packet) -> Result<NodeId, CommonError> {
+        if let Err(e) = validate_route(&packet.routing_header) {
+            log_error!(self.get_id(), "Refusing to forward a packet with an invalid route: {}", e);
+            return Err(e);
+        }
+        let Some(next_hop_id) = next_hop(&packet.routing_header) else {
+            log_error!(self.get_id(), "The route of the packet is exhausted: {:?}", packet.routing_header);
+            return Err(CommonError::RouteExhausted);
+        };
+        // Only the packets relayed for other nodes can loop through this one
+        if packet.routing_header.hop_index > 0 && self.exceeds_max_forwards(packet.session_id) {
+            self.drop_looping_packet(packet.clone());
+            return Err(CommonError::ForwardLimitExceeded(packet.session_id));
+        }
+        if self.is_blocked(next_hop_id) {
+            log_status!(self.get_id(), "The link towards next hop {:?} is blocked", next_hop_id);
+            return Err(CommonError::NextHopUnreachable(next_hop_id));
+        }
+        packet.routing_header.hop_index += 1;
+        Ok(next_hop_id)
+    }
+    
+    /// Empties the packet receiver of a node that has just started crashing.
+    ///
+    /// Following the crash semantics of the protocol, the packets still queued are not lost
+    /// silently: message fragments are answered with an `ErrorInRouting` Nack carrying this
+    /// node's id, Acks, Nacks and flood responses are forwarded normally, and flood requests
+    /// are dropped. Packets arriving after the receiver has been emptied are not processed.
+    fn crash(&mut self) {
+        let pending: Vec<Packet> = self.get_packet_receiver().try_iter().collect();
+        for packet in pending {
+            match packet.pack_type {
+                PacketType::MsgFragment(_) => {
+                    let nack = self.build_nack(packet, NackType::ErrorInRouting(self.get_id()));
+                    if let Err(e) = self.forward_packet(nack) {
+                        log_error!(self.get_id(), "Failed to send the ErrorInRouting Nack: {:?}", e);
+                    }
+                }
+                PacketType::FloodRequest(_) => self.drop_packet(packet, DropReason::Crashed),
+                PacketType::Ack(_) | PacketType::Nack(_) | PacketType::FloodResponse(_) => {
+                    if let Err(e) = self.forward_packet(packet) {
+                        log_error!(self.get_id(), "Failed to forward a packet while crashing: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Randomly drops a message fragment according to the node's packet drop rate.
+    ///
+    /// Only `MsgFragment` packets can be dropped: control packets (Ack, Nack, flood requests
+    /// and responses) are never affected by the packet drop rate. A dropped fragment goes
+    /// through `drop_packet`, which reports it and sends a `Dropped` Nack back.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet about to be forwarded.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the packet was dropped and must not be forwarded.
+    fn maybe_drop(&mut self, packet: &Packet) -> bool {
+        if !matches!(packet.pack_type, PacketType::MsgFragment(_)) {
+            return false;
+        }
+        let pdr = self.get_pdr();
+        if pdr <= 0.0 || self.get_random_generator().gen::<f32>() >= pdr {
+            return false;
+        }
+        self.drop_packet(packet.clone(), DropReason::RandomDrop);
+        true
+    }
+    
+    /// Drops a packet, notifying the simulation controller and the sender.
+    ///
+    /// A `PacketDropped` event is sent to the simulation controller, the drop is reported with
+    /// `report_drop`, counted in the node metrics and a `Dropped` Nack is sent back along the
+    /// packet's route. Flood requests
+    /// are never Nacked: they are only reported to the simulation controller.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The dropped packet.
+    /// * `reason` - Why the packet is dropped.
+    fn drop_packet(&mut self, packet: Packet, reason: DropReason) {
+        log_status!(self.get_id(), "Dropping packet ({:?}): {:?}", reason, packet);
+        self.get_metrics().dropped += 1;
+        self.report_drop(&packet, reason);
+        if let Err(e) = self.emit_event(DroneEvent::PacketDropped(packet.clone())) {
+            log_error!(self.get_id(), "Failed to send PacketDropped event: {:?}", e);
+        }
+        
+        if matches!(packet.pack_type, PacketType::FloodRequest(_)) {
+            return;
+        }
+        let nack = self.build_nack(packet, NackType::Dropped);
+        if let Err(e) = self.forward_packet(nack) {
+            log_error!(self.get_id(), "Failed to send the Dropped Nack: {:?}", e);
         }
     }
     
@@ -190,14 +1453,10 @@ pub trait NetworkNode {
     /// # Returns
     ///
     /// A new packet representing the Nack.
-    fn build_nack(&self, packet: Packet, nack_type: NackType) -> Packet {
-        let fragment_index = match &packet.pack_type {
-            PacketType::MsgFragment(fragment) => fragment.fragment_index,
-            _ => 0,
-        };
-        
+    fn build_nack(&mut self, packet: Packet, nack_type: NackType) -> Packet {
+        self.get_metrics().nacks_built += 1;
         let nack = Nack {
-            fragment_index,
+            fragment_index: fragment_index_of(&packet),
             nack_type,
         };
         
@@ -211,6 +1470,59 @@ pub trait NetworkNode {
         response
     }
     
+    /// Checks that this node is the current hop of the packet's routing header.
+    ///
+    /// Per the protocol, a node receiving a routed packet must find its own id at
+    /// `hops[hop_index]`. Implementations of `handle_routed_packet` should call this before
+    /// forwarding, to avoid silently mis-forwarding packets that follow a stale route.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The received packet.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if this node is the current hop, otherwise an `UnexpectedRecipient` Nack
+    /// carrying this node's id, ready to be wrapped in a packet with `build_nack`.
+    fn check_recipient(&self, packet: &Packet) -> Result<(), Nack> {
+        let current_hop = packet
+            .routing_header
+            .hops
+            .get(packet.routing_header.hop_index);
+        if current_hop == Some(&self.get_id()) {
+            Ok(())
+        } else {
+            Err(Nack {
+                fragment_index: fragment_index_of(packet),
+                nack_type: NackType::UnexpectedRecipient(self.get_id()),
+            })
+        }
+    }
+    
+    /// Constructs the `UnexpectedRecipient` Nack answering a packet this node should not have received.
+    ///
+    /// Since this node is not `hops[hop_index]`, the Nack cannot simply follow the reversed
+    /// route: it goes back from this node through the hops that preceded `hop_index`, so
+    /// the source can detect and repair its stale route. Implementations of
+    /// `handle_routed_packet` should send it when `check_recipient` fails.
+    /// The misrouted packet is reported with `report_drop`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The misrouted packet.
+    ///
+    /// # Returns
+    ///
+    /// The Nack packet, ready to be sent with `forward_packet`.
+    fn nack_unexpected_recipient(&mut self, mut packet: Packet) -> Packet {
+        self.report_drop(&packet, DropReason::UnexpectedRecipient);
+        let header = &mut packet.routing_header;
+        header.hops.truncate(header.hop_index);
+        header.hops.push(self.get_id());
+        header.hop_index = header.hops.len() - 1;
+        self.build_nack(packet, NackType::UnexpectedRecipient(self.get_id()))
+    }
+    
     /// Constructs an acknowledgement (Ack) packet corresponding to a message fragment packet.
     ///
     /// The function extracts the fragment index from the original packet, builds an Ack,
@@ -220,19 +1532,16 @@ pub trait NetworkNode {
     ///
     /// * `packet` - The original fragment packet to acknowledge.
     ///
-    /// # Panics
+    /// # Returns
     ///
-    /// Panics if the provided packet is not a fragment packet.
-    fn build_ack(&self, packet: Packet) -> Packet {
+    /// The Ack packet, or `CommonError::NonFragmentPacket` if the provided packet
+    /// is not a fragment packet.
+    fn build_ack(&self, packet: Packet) -> Result<Packet, CommonError> {
         // 1. Keep in the ack the fragment index if the packet contains a fragment
-        let frag_index: u64;
-        
-        if let PacketType::MsgFragment(fragment) = &packet.pack_type {
-            frag_index = fragment.fragment_index;
-        } else {
-            eprintln!("Error : attempt of building an ack on a non-fragment packet.");
-            panic!()
-        }
+        let frag_index: u64 = match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => fragment.fragment_index,
+            _ => return Err(CommonError::NonFragmentPacket),
+        };
         
         // 2. Build the Ack instance of the packet to return
         let ack: Ack = Ack {
@@ -252,326 +1561,3434 @@ pub trait NetworkNode {
         self.reverse_packet_routing_direction(&mut packet);
         
         // 5. Return the packet
-        packet
+        Ok(packet)
     }
     
-    /// Processes a flood request packet.
+    /// Returns the buffer of fragments sent by this node and not yet acknowledged, if it keeps one.
     ///
-    /// Depending on whether the flood request has been seen before or if there are no other neighbours,
-    /// the function either builds a flood response or broadcasts the flood request to eligible neighbours.
+    /// The default implementation returns `None`. Nodes sending messages (clients and servers)
+    /// can return their own buffer to get dropped fragments retransmitted automatically:
+    /// they should `record` every fragment they send.
+    fn get_sent_fragment_buffer(&mut self) -> Option<&mut SentFragmentBuffer> {
+        None
+    }
+    
+    /// Returns the emitter of the heartbeats of this node, if it sends any.
     ///
-    /// # Arguments
+    /// The default implementation returns `None`: the node ignores `Command::Heartbeat` and
+    /// `heartbeat_if_due` does nothing. Nodes watched by the simulation controller return
+    /// their emitter, and call `heartbeat_if_due` from their main loop.
+    fn get_heartbeat_emitter(&mut self) -> Option<&mut HeartbeatEmitter> {
+        None
+    }
+    
+    /// Emits a heartbeat if the node has a heartbeat emitter and its interval has elapsed.
     ///
-    /// * `packet` - The flood request packet to handle.
-    /// * `node_type` - The type of the node processing the request.
-    fn handle_flood_request(&mut self, packet: Packet, node_type: NodeType) {
-        // Check if the flood request should be broadcast or turned into a flood response and sent back
-        if let PacketType::FloodRequest(mut flood_request) = packet.pack_type.clone() {
-            let who_sent_me_this_flood_request = flood_request.path_trace.last().expect("Failed to get the last node of the path").0;
-            
-            // Add self to the path trace
-            flood_request.path_trace.push((self.get_id(), node_type));
-            
-            // 1. Process some tests on the node and its neighbours to know how to handle the flood request
-            
-            // a. Check if the node has already received the flood request
-            let flood_request_is_already_received: bool = self
-                .get_seen_flood_ids()
-                .iter()
-                .any(|id| *id == (
-                    flood_request.initiator_id.to_string() + "_" + flood_request.flood_id.to_string().as_str()
-                ));
-            
-            // b. Check if the node has a neighbour, excluding the one from which it received the flood request
-            
-            // Check if the updated neighbours list is empty
-            
-            // If I have only one neighbour, I must have received this message from it and I don't have anybody else to forward it to
-            let has_no_neighbour: bool = self.get_packet_send().len() == 1;
-            
-            // 2. Check if the flood request should be sent back as a flood response or broadcasted as is
-            if flood_request_is_already_received || has_no_neighbour {
-                // A flood response should be created and sent
-                
-                // a. Create a built response based on the flood request
-                let flood_response_packet =
-                    self.build_flood_response(packet, flood_request.path_trace);
-                
-                // Forward the flood response packet
-                self.forward_packet(flood_response_packet);
-            } else {
-                // The packet should be broadcast
-                self.get_seen_flood_ids().insert(
-                    flood_request.initiator_id.to_string() + "_" + flood_request.flood_id.to_string().as_str()
-                );
-                
-                // Create the new packet with the updated flood_request
-                let updated_packet = Packet {
-                    pack_type: PacketType::FloodRequest(flood_request),
-                    routing_header: packet.routing_header,
-                    session_id: packet.session_id,
-                };
-                // Broadcast the updated packet
-                self.broadcast_packet(updated_packet, who_sent_me_this_flood_request);
-            }
-        } else {
-            eprintln!("Error: the packet to be broadcast is not a flood request.");
+    /// Meant to be called on every iteration of the node's main loop.
+    fn heartbeat_if_due(&mut self) {
+        let id = self.get_id();
+        if let Some(emitter) = self.get_heartbeat_emitter() {
+            emitter.beat_if_due(id);
         }
     }
     
-    /// Builds a flood response packet from a flood request packet and the provided path trace.
+    /// Returns the channel the drop records of this node are sent on, if it sends any.
     ///
-    /// The function reverses the path trace to generate a routing header that guides the
-    /// response back to the originator.
+    /// The default implementation returns `None`: `report_drop` does nothing.
+    fn get_drop_record_send(&self) -> Option<&Sender<DropRecord>> {
+        None
+    }
+    
+    /// Sends a `DropRecord` for a dropped packet, if the node has a drop record channel.
+    ///
+    /// Called by `drop_packet`. Components dropping packets without `drop_packet`, e.g. for lack
+    /// of a route, call it directly.
     ///
     /// # Arguments
     ///
-    /// * `packet` - The original flood request packet.
-    /// * `path_trace` - A vector of tuples containing node IDs and their types that represent the path.
+    /// * `packet` - The dropped packet.
+    /// * `reason` - Why the packet is dropped.
+    fn report_drop(&self, packet: &Packet, reason: DropReason) {
+        if let Some(drop_record_send) = self.get_drop_record_send() {
+            let record = DropRecord {
+                node_id: self.get_id(),
+                reason,
+                packet: packet.clone(),
+            };
+            if drop_record_send.send(record).is_err() {
+                log_error!(self.get_id(), "Failed to send the drop record");
+            }
+        }
+    }
+    
+    /// Returns the recorder of the packets received by this node, if they are recorded.
     ///
-    /// # Returns
+    /// The default implementation returns `None`. Nodes return a `PacketRecorder` to record
+    /// every packet passed to `handle_packet`, so that the recording can be replayed later.
+    fn get_packet_recorder(&mut self) -> Option<&mut PacketRecorder> {
+        None
+    }
+    
+    /// Returns the neighbours this node currently refuses to send packets to, if it keeps a
+    /// blocklist.
     ///
-    /// A new packet representing the flood response.
+    /// The default implementation returns `None`: no neighbour is ever blocked. Nodes simulating
+    /// link failures return the set changed by `set_broadcast_blocklist`.
+    fn broadcast_blocklist_ref(&self) -> Option<&HashSet<NodeId>> {
+        None
+    }
+    
+    /// Returns a mutable reference to the blocklist of this node, see `broadcast_blocklist_ref`.
+    fn get_broadcast_blocklist(&mut self) -> Option<&mut HashSet<NodeId>> {
+        None
+    }
+    
+    /// Replaces the neighbours this node refuses to send packets to, keeping their channels.
     ///
-    /// # Panics
+    /// `broadcast_packet` skips the blocked neighbours, and `forward_packet` treats a blocked
+    /// next hop as unreachable, so that a drone answers with a Nack as for a missing channel.
+    /// An empty set restores every link. Logs an error if the node keeps no blocklist.
     ///
-    /// Panics if the packet is not a flood request.
-    fn build_flood_response(
-        &mut self,
-        packet: Packet,
-        path_trace: Vec<(NodeId, NodeType)>,
-    ) -> Packet {
-        if let PacketType::FloodRequest(flood_request) = packet.pack_type {
-            let mut route_back: Vec<NodeId> = path_trace.iter().map(|tuple| tuple.0).collect();
-            route_back.reverse(); // Reverse the route for sending back the response
-            
-            let new_routing_header = SourceRoutingHeader {
-                hop_index: 1,
-                hops: route_back,
-            };
-            
-            Packet {
-                pack_type: PacketType::FloodResponse(wg_2024::packet::FloodResponse {
-                    flood_id: flood_request.flood_id,
-                    path_trace,
-                }),
-                routing_header: new_routing_header,
-                session_id: flood_request.flood_id,
-            }
-        } else {
-            panic!("Error! Attempt to build flood response from non-flood request packet");
+    /// # Arguments
+    ///
+    /// * `blocked` - The ids of the neighbours to block.
+    fn set_broadcast_blocklist(&mut self, blocked: HashSet<NodeId>) {
+        let id = self.get_id();
+        match self.get_broadcast_blocklist() {
+            Some(blocklist) => *blocklist = blocked,
+            None => log_error!(id, "Error! The current node {} does not support a blocklist.", id),
         }
     }
     
-    /// Broadcasts a packet to all neighbouring nodes except the one from which the packet was received.
-    ///
-    /// For each eligible neighbour, the function updates the routing header to reflect the direct path
-    /// from the current node to that neighbour and sends a simulation event.
+    /// Returns whether the link towards a neighbour is blocked, see `set_broadcast_blocklist`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the neighbour.
+    fn is_blocked(&self, id: NodeId) -> bool {
+        self.broadcast_blocklist_ref()
+            .is_some_and(|blocklist| blocklist.contains(&id))
+    }
+    
+    /// Returns the maximum number of packets of a single session this node relays.
+    ///
+    /// Packets do not carry a time to live: this bounds how long a packet caught in a routing
+    /// loop keeps circulating, independently of `validate_route`. The count covers every packet
+    /// of the session (fragments, Acks and Nacks), so the limit must stay well above the number
+    /// of fragments of the largest message. Only enforced if `get_forward_counts` returns some.
+    fn get_max_forwards(&self) -> u32 {
+        DEFAULT_MAX_FORWARDS
+    }
+    
+    /// Returns the number of packets relayed by this node per session, if it counts them.
+    ///
+    /// The default implementation returns `None`: the number of forwards is not limited.
+    fn get_forward_counts(&mut self) -> Option<&mut ForwardCounts> {
+        None
+    }
+    
+    /// Counts one more relayed packet for a session, and checks it against `get_max_forwards`.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session of the relayed packet.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the packet must not be forwarded, since the node already relayed the maximum
+    /// number of packets of the session.
+    fn exceeds_max_forwards(&mut self, session_id: u64) -> bool {
+        let max_forwards = self.get_max_forwards();
+        match self.get_forward_counts() {
+            Some(forward_counts) => forward_counts.increment(session_id) > max_forwards,
+            None => false,
+        }
+    }
+    
+    /// Drops a packet exceeding the forward limit of its session.
+    ///
+    /// Message fragments are answered with an `ErrorInRouting` Nack carrying this node's id,
+    /// so that the sender looks for another route; other packets are only dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The dropped packet.
+    fn drop_looping_packet(&mut self, packet: Packet) {
+        log_error!(
+            self.get_id(),
+            "Dropping a packet of session {} over the forward limit: it is likely looping",
+            packet.session_id
+        );
+        self.get_metrics().dropped += 1;
+        self.report_drop(&packet, DropReason::ForwardLimit);
+        if matches!(packet.pack_type, PacketType::MsgFragment(_)) {
+            let nack = self.build_nack(packet, NackType::ErrorInRouting(self.get_id()));
+            if let Err(e) = self.forward_packet(nack) {
+                log_error!(self.get_id(), "Failed to send the ErrorInRouting Nack: {:?}", e);
+            }
+        }
+    }
+    
+    /// Returns the cache of the routes known to work from this node, if it keeps one.
+    ///
+    /// The default implementation returns `None`. Nodes sending messages return a `RouteCache`:
+    /// `handle_packet` caches the routes followed back by the Acks and flood responses addressed
+    /// to this node, and `handle_nack` drops the routes through a node reported failing.
+    fn get_route_cache(&mut self) -> Option<&mut RouteCache> {
+        None
+    }
+    
+    /// Caches the route back to the node that sent a packet addressed to this node, since the
+    /// packet just proved it works.
+    ///
+    /// A route already cached towards the same node is only replaced by a route that is not
+    /// longer. Does nothing if the node has no route cache, or if the route is invalid (see
+    /// `validate_route`), since every later send on it would fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The routing header of the received packet.
+    fn cache_return_route(&mut self, header: &SourceRoutingHeader) {
+        let id = self.get_id();
+        if let Err(e) = validate_route(header) {
+            log_error!(id, "Not caching the invalid route {:?}: {}", header.hops, e);
+            return;
+        }
+        let route: Vec<NodeId> = header.hops.iter().rev().copied().collect();
+        let (Some(&first), Some(&dest)) = (route.first(), route.last()) else {
+            return;
+        };
+        if first != id || dest == id {
+            return;
+        }
+        if let Some(route_cache) = self.get_route_cache() {
+            if route_cache.get(dest).is_none_or(|cached| route.len() <= cached.len()) {
+                route_cache.insert(dest, route);
+            }
+        }
+    }
+    
+    /// Returns the tracker of the last flood initiated by this node, if it tracks its floods.
+    ///
+    /// The default implementation returns `None`. Nodes discovering the network (clients and
+    /// servers) return a `FloodTracker`: `initiate_flood` restarts it for every new flood, and
+    /// `handle_packet` feeds it the flood responses addressed to this node.
+    fn get_flood_tracker(&mut self) -> Option<&mut FloodTracker> {
+        None
+    }
+    
+    /// Returns the set of fragments already processed by this node, if it keeps one.
+    ///
+    /// The default implementation returns `None`, in which case `is_duplicate` never
+    /// reports a duplicate. Nodes receiving messages can return a `SeenFragmentCache`.
+    fn get_seen_fragments(&mut self) -> Option<&mut SeenFragmentCache> {
+        None
+    }
+    
+    /// Checks whether a fragment was already processed, and records it as processed otherwise.
+    ///
+    /// Implementations of `handle_routed_packet` can use this to skip fragments received
+    /// twice (e.g. after a retransmission) while still sending an Ack for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session of the received fragment.
+    /// * `fragment_index` - The index of the received fragment.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the fragment was already processed.
+    fn is_duplicate(&mut self, session_id: u64, fragment_index: u64) -> bool {
+        match self.get_seen_fragments() {
+            Some(seen_fragments) => !seen_fragments.insert((session_id, fragment_index)),
+            None => false,
+        }
+    }
+    
+    /// Processes a received Ack by evicting the acknowledged fragment from the sent fragment buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `ack` - The received Ack.
+    /// * `session_id` - The session id of the packet carrying the Ack.
+    fn handle_ack(&mut self, ack: &Ack, session_id: u64) {
+        if let Some(buffer) = self.get_sent_fragment_buffer() {
+            buffer.ack(session_id, ack.fragment_index);
+        }
+    }
+    
+    /// Interprets a received Nack and dispatches it to the matching hook.
+    ///
+    /// The default implementation of every hook only logs the Nack: components override
+    /// the hooks they care about, e.g. `on_dropped_fragment` to retransmit the fragment
+    /// or `on_routing_error` to compute a new route. An `ErrorInRouting` Nack also drops the
+    /// cached routes through the failing node, see `get_route_cache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `nack` - The received Nack.
+    /// * `session_id` - The session id of the packet carrying the Nack.
+    fn handle_nack(&mut self, nack: &Nack, session_id: u64) {
+        match nack.nack_type {
+            NackType::Dropped => self.on_dropped_fragment(session_id, nack.fragment_index),
+            NackType::ErrorInRouting(node_id) => {
+                if let Some(route_cache) = self.get_route_cache() {
+                    route_cache.invalidate(node_id);
+                }
+                self.on_routing_error(session_id, nack.fragment_index, node_id)
+            }
+            NackType::DestinationIsDrone => {
+                self.on_destination_is_drone(session_id, nack.fragment_index)
+            }
+            NackType::UnexpectedRecipient(node_id) => {
+                self.on_unexpected_recipient(session_id, nack.fragment_index, node_id)
+            }
+        }
+    }
+    
+    /// Called by `handle_nack` when a fragment was dropped on its way.
+    ///
+    /// If the node keeps a `SentFragmentBuffer`, the default implementation also
+    /// retransmits the dropped fragment along its original route.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session of the dropped fragment.
+    /// * `fragment_index` - The index of the dropped fragment.
+    fn on_dropped_fragment(&mut self, session_id: u64, fragment_index: u64) {
+        log_status!(
+            self.get_id(),
+            "Fragment {} of session {} was dropped",
+            fragment_index,
+            session_id
+        );
+        
+        // Retransmit the fragment if the node keeps its sent fragments
+        let packet = self
+            .get_sent_fragment_buffer()
+            .and_then(|buffer| buffer.get(session_id, fragment_index).cloned());
+        if let Some(mut packet) = packet {
+            // The retransmission starts again from this node, the first hop of the route
+            packet.routing_header.hop_index = 0;
+            if let Err(e) = self.forward_packet(packet) {
+                log_error!(self.get_id(), "Failed to retransmit the dropped fragment: {:?}", e);
+            }
+        }
+    }
+    
+    /// Called by `handle_nack` when a node on the route had no channel towards the next hop.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session of the undelivered fragment.
+    /// * `fragment_index` - The index of the undelivered fragment.
+    /// * `node_id` - The node that could not forward the fragment.
+    fn on_routing_error(&mut self, session_id: u64, fragment_index: u64, node_id: NodeId) {
+        log_status!(
+            self.get_id(),
+            "Fragment {} of session {} could not be routed by node {}",
+            fragment_index,
+            session_id,
+            node_id
+        );
+    }
+    
+    /// Called by `handle_nack` when the route of a fragment ended on a drone.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session of the undelivered fragment.
+    /// * `fragment_index` - The index of the undelivered fragment.
+    fn on_destination_is_drone(&mut self, session_id: u64, fragment_index: u64) {
+        log_status!(
+            self.get_id(),
+            "Fragment {} of session {} was routed to a drone",
+            fragment_index,
+            session_id
+        );
+    }
+    
+    /// Called by `handle_nack` when a fragment reached a node that was not its current hop.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session of the undelivered fragment.
+    /// * `fragment_index` - The index of the undelivered fragment.
+    /// * `node_id` - The node that unexpectedly received the fragment.
+    fn on_unexpected_recipient(&mut self, session_id: u64, fragment_index: u64, node_id: NodeId) {
+        log_status!(
+            self.get_id(),
+            "Fragment {} of session {} unexpectedly reached node {}",
+            fragment_index,
+            session_id,
+            node_id
+        );
+    }
+    
+    /// Processes a flood request packet.
+    ///
+    /// Depending on whether the flood request has been seen before or if there are no other neighbours,
+    /// the function either builds a flood response or broadcasts the flood request to eligible neighbours.
+    /// A flood request whose path trace already contains this node went through a routing loop:
+    /// it is treated as already seen. The full path trace is kept in the flood response, so that
+    /// the initiator learns every link of the loop.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The flood request packet to handle.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                node_id = self.get_id(),
+                session_id = packet.session_id,
+                packet_type = packet_type_name(&packet.pack_type),
+            )
+        )
+    )]
+    fn handle_flood_request(&mut self, packet: Packet) {
+        // Check if the flood request should be broadcast or turned into a flood response and sent back
+        if let PacketType::FloodRequest(mut flood_request) = packet.pack_type.clone() {
+            let Some(&(who_sent_me_this_flood_request, _)) = flood_request.path_trace.last() else {
+                log_error!(self.get_id(), "Received a flood request with an empty path trace: {:?}", flood_request);
+                self.drop_packet(packet, DropReason::Malformed);
+                return;
+            };
+            
+            // Check whether the request looped back to this node, then add self to the path trace
+            let is_looping = flood_request
+                .path_trace
+                .iter()
+                .any(|(node_id, _)| *node_id == self.get_id());
+            flood_request.path_trace.push((self.get_id(), self.get_node_type()));
+            
+            // 1. Process some tests on the node and its neighbours to know how to handle the flood request
+            
+            // a. Check if the node has already received the flood request
+            self.get_metrics().flood_requests_seen += 1;
+            let flood_key = (flood_request.initiator_id, flood_request.flood_id);
+            let flood_request_is_already_received: bool =
+                is_looping || self.get_seen_flood_ids().contains(&flood_key);
+            
+            // b. Check if the node has a neighbour, excluding the one from which it received the flood request
+            
+            // Check if the updated neighbours list is empty
+            
+            // If I have only one neighbour, I must have received this message from it and I don't have anybody else to forward it to
+            let has_no_neighbour: bool = self.packet_send_ref().len() == 1;
+            
+            // 2. Check if the flood request should be sent back as a flood response or broadcasted as is
+            if flood_request_is_already_received || has_no_neighbour {
+                // A flood response should be created and sent
+                
+                // a. Create a built response based on the flood request
+                let flood_response_packet =
+                    self.build_flood_response(packet, flood_request.path_trace);
+                
+                // Forward the flood response packet
+                if let Err(e) = self.forward_packet(flood_response_packet) {
+                    log_error!(self.get_id(), "Failed to forward the flood response: {:?}", e);
+                }
+            } else {
+                // The packet should be broadcast
+                self.get_seen_flood_ids().insert(flood_key);
+                
+                // Create the new packet with the updated flood_request
+                let updated_packet = Packet {
+                    pack_type: PacketType::FloodRequest(flood_request),
+                    routing_header: packet.routing_header,
+                    session_id: packet.session_id,
+                };
+                // Broadcast the updated packet
+                if let Err(e) = self.broadcast_packet(updated_packet, who_sent_me_this_flood_request) {
+                    log_error!(self.get_id(), "Failed to broadcast the flood request: {:?}", e);
+                }
+            }
+        } else {
+            eprintln!("Error: the packet to be broadcast is not a flood request.");
+        }
+    }
+    
+    /// Returns a fresh session id for an outgoing message, drawn from the node's random generator.
+    ///
+    /// Every message sent by a node should use this, so that session ids are consistent across
+    /// components and unlikely to collide. Flood responses are the exception: they keep the
+    /// flood id as session id (see `build_flood_response`) so they can be matched to their flood.
+    fn next_session_id(&mut self) -> u64 {
+        self.get_random_generator().next_u64()
+    }
+    
+    /// Returns the id of a new flood initiated by this node.
+    ///
+    /// Ids are increasing for a given node, starting at 1. They are not unique across the
+    /// network: the `(initiator_id, flood_id)` pair, i.e. the `FloodKey` under which nodes
+    /// remember the floods they have seen, is. Two initiators can therefore both use flood id 1.
+    fn next_flood_id(&mut self) -> u64 {
+        let last_flood_id = self.get_last_flood_id();
+        *last_flood_id += 1;
+        *last_flood_id
+    }
+    
+    /// Starts a flood to discover the network.
+    ///
+    /// Builds a flood request with a fresh flood id from `next_flood_id` and a path trace holding
+    /// only this node, records the flood as seen so that the request is not processed again when
+    /// it comes back, and broadcasts it to every neighbour. The flood id is also the session id
+    /// of the request, as for the flood responses answering it.
+    ///
+    /// # Returns
+    ///
+    /// The id of the flood, to match the flood responses, or `CommonError::ControllerUnreachable`
+    /// if the simulation controller is unreachable.
+    fn initiate_flood(&mut self) -> Result<u64, CommonError> {
+        let id = self.get_id();
+        let flood_id = self.next_flood_id();
+        self.get_seen_flood_ids().insert((id, flood_id));
+        if let Some(tracker) = self.get_flood_tracker() {
+            tracker.restart(flood_id);
+        }
+        
+        let packet = Packet {
+            pack_type: PacketType::FloodRequest(FloodRequest {
+                flood_id,
+                initiator_id: id,
+                path_trace: vec![(id, self.get_node_type())],
+            }),
+            routing_header: build_routing_header(vec![id]),
+            session_id: flood_id,
+        };
+        // No neighbour has the id of this node: the request is sent to all of them
+        self.broadcast_packet(packet, id)?;
+        Ok(flood_id)
+    }
+    
+    /// Picks a next hop at random among several candidates, proportionally to their weights.
+    ///
+    /// A candidate with weight `0` is never picked. The draw uses the node's random generator,
+    /// so a seeded generator makes the selection reproducible.
+    ///
+    /// # Arguments
+    ///
+    /// * `candidates` - The candidate next hops, each with its weight.
+    ///
+    /// # Returns
+    ///
+    /// The chosen next hop, or `None` if there are no candidates or all weights are `0`.
+    fn weighted_next_hop(&mut self, candidates: &[(NodeId, u32)]) -> Option<NodeId> {
+        let total: u64 = candidates.iter().map(|&(_, weight)| u64::from(weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut draw = self.get_random_generator().gen_range(0..total);
+        for &(node_id, weight) in candidates {
+            if draw < u64::from(weight) {
+                return Some(node_id);
+            }
+            draw -= u64::from(weight);
+        }
+        None
+    }
+    
+    /// Builds a flood response packet from a flood request packet and the provided path trace.
+    ///
+    /// The function reverses the path trace to generate a routing header that guides the
+    /// response back to the originator. If the path trace went through a loop, only the part up to
+    /// this node's first occurrence is used for the routing header, so that the route stays valid,
+    /// while the response still carries the full path trace. The flood id is used as session id
+    /// of the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The original flood request packet.
+    /// * `path_trace` - A vector of tuples containing node IDs and their types that represent the path.
+    ///
+    /// # Returns
+    ///
+    /// A new packet representing the flood response.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the packet is not a flood request.
+    fn build_flood_response(
+        &mut self,
+        packet: Packet,
+        path_trace: Vec<(NodeId, NodeType)>,
+    ) -> Packet {
+        if let PacketType::FloodRequest(flood_request) = packet.pack_type {
+            self.get_metrics().flood_responses_built += 1;
+            // Only route back through the loop-free prefix of the path trace
+            let loop_free_len = path_trace
+                .iter()
+                .position(|(node_id, _)| *node_id == self.get_id())
+                .map_or(path_trace.len(), |position| position + 1);
+            let mut route_back: Vec<NodeId> =
+                path_trace[..loop_free_len].iter().map(|tuple| tuple.0).collect();
+            route_back.reverse(); // Reverse the route for sending back the response
+            
+            let new_routing_header = build_routing_header(route_back);
+            
+            Packet {
+                pack_type: PacketType::FloodResponse(wg_2024::packet::FloodResponse {
+                    flood_id: flood_request.flood_id,
+                    path_trace,
+                }),
+                routing_header: new_routing_header,
+                session_id: flood_request.flood_id,
+            }
+        } else {
+            panic!("Error! Attempt to build flood response from non-flood request packet");
+        }
+    }
+    
+    /// Broadcasts a packet to all neighbouring nodes except the one from which the packet was received.
+    ///
+    /// For each eligible neighbour, the function updates the routing header to reflect the direct path
+    /// from the current node to that neighbour and sends a simulation event. Blocked neighbours
+    /// (see `set_broadcast_blocklist`) are skipped.
     ///
     /// # Arguments
     ///
     /// * `packet` - The packet to broadcast.
     /// * `who_i_received_the_packet_from` - The node ID from which the original packet was received.
-    fn broadcast_packet(&mut self, packet: Packet, who_i_received_the_packet_from: NodeId) {
-        // Copy the list of neighbours and remove the neighbour drone that sent the flood request
-        let neighbours: HashMap<NodeId, Sender<Packet>> = self
-            .get_packet_send()
-            .iter()
-            .filter(|(&key, _)| key != who_i_received_the_packet_from)
-            .map(|(k, v)| (*k, v.clone()))
+    ///
+    /// # Returns
+    ///
+    /// `CommonError::ControllerUnreachable` if the simulation controller is unreachable, in which
+    /// case the remaining neighbours are skipped. Failures towards single neighbours are only logged.
+    fn broadcast_packet(
+        &mut self,
+        packet: Packet,
+        who_i_received_the_packet_from: NodeId,
+    ) -> Result<(), CommonError> {
+        // List the neighbours, except the one that sent the flood request. Only their ids are
+        // copied, so that neither the map nor the senders are cloned.
+        let neighbours: Vec<NodeId> = self
+            .packet_send_ref()
+            .keys()
+            .copied()
+            .filter(|&node_id| node_id != who_i_received_the_packet_from && !self.is_blocked(node_id))
+            .collect();
+        
+        // Iterate on the neighbours list
+        for node_id in neighbours {
+            let mut packet_to_send = packet.clone();
+            packet_to_send.routing_header = build_routing_header(vec![self.get_id(), node_id]);
+            // Send a clone of the packet and a simulation event
+            match self.forward_packet(packet_to_send) {
+                Err(e @ CommonError::ControllerUnreachable) => return Err(e),
+                Err(e) => log_error!(self.get_id(), "Failed to send packet to NodeId {:?}: {:?}", node_id, e),
+                Ok(()) => {}
+            }
+        }
+        Ok(())
+    }
+    
+    /// Broadcasts a packet like `broadcast_packet`, sending to the neighbours from a thread pool.
+    ///
+    /// Each copy goes through the same checks as in `broadcast_packet` (see `prepare_forward`),
+    /// sequentially; then the `PacketSent` events and the sends to the neighbours run in
+    /// parallel on rayon's global thread pool, each event preceding its send as in
+    /// `forward_packet`. Requires the `parallel` feature.
+    ///
+    /// Sends on crossbeam channels are cheap, so the thread pool only pays off for nodes with
+    /// many neighbours, and the crossover degree depends on the machine. The `broadcast`
+    /// benchmark times both versions on nodes of 5, 10, 20 and 50 neighbours: run
+    /// `cargo bench --features parallel,test-util --bench broadcast` on the target machine
+    /// before switching a node to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to broadcast.
+    /// * `who_i_received_the_packet_from` - The node ID from which the original packet was received.
+    ///
+    /// # Returns
+    ///
+    /// The neighbours the packet could not be sent to, with the corresponding error, or
+    /// `CommonError::ControllerUnreachable` if the simulation controller is unreachable, in
+    /// which case no packet is sent.
+    #[cfg(feature = "parallel")]
+    fn broadcast_packet_parallel(
+        &mut self,
+        packet: Packet,
+        who_i_received_the_packet_from: NodeId,
+    ) -> Result<Vec<(NodeId, CommonError)>, CommonError> {
+        let id = self.get_id();
+        let neighbours: Vec<NodeId> = self
+            .packet_send_ref()
+            .keys()
+            .copied()
+            .filter(|&node_id| node_id != who_i_received_the_packet_from && !self.is_blocked(node_id))
+            .collect();
+        
+        let mut errors = Vec::new();
+        let mut prepared = Vec::with_capacity(neighbours.len());
+        for node_id in neighbours {
+            let mut packet_to_send = packet.clone();
+            packet_to_send.routing_header = build_routing_header(vec![id, node_id]);
+            match self.prepare_forward(&mut packet_to_send) {
+                Ok(next_hop_id) => prepared.push((next_hop_id, packet_to_send)),
+                Err(e) => errors.push((node_id, e)),
+            }
+        }
+        
+        let sim_contr_send = self.get_sim_contr_send();
+        let packet_send = self.packet_send_ref();
+        let results: Vec<(NodeId, Result<(), CommonError>)> = prepared
+            .into_par_iter()
+            .map(|(next_hop_id, packet_to_send)| {
+                let result = match packet_send.get(&next_hop_id) {
+                    Some(sender) => {
+                        send_to_next_hop(id, sim_contr_send, next_hop_id, sender, packet_to_send)
+                    }
+                    None => Err(CommonError::NextHopUnreachable(next_hop_id)),
+                };
+                (next_hop_id, result)
+            })
+            .collect();
+        
+        let mut sent = 0;
+        let mut controller_unreachable = false;
+        for (node_id, result) in results {
+            match result {
+                Ok(()) => sent += 1,
+                Err(CommonError::ControllerUnreachable) => controller_unreachable = true,
+                Err(e) => {
+                    log_error!(id, "Failed to send packet to NodeId {:?}: {:?}", node_id, e);
+                    errors.push((node_id, e));
+                }
+            }
+        }
+        self.get_metrics().forwarded += sent;
+        if controller_unreachable {
+            return Err(CommonError::ControllerUnreachable);
+        }
+        Ok(errors)
+    }
+    
+    /// Sends a packet to a chosen subset of the neighbouring nodes.
+    ///
+    /// As in `broadcast_packet`, each target receives its own copy with a direct routing header
+    /// from the current node, and a simulation event is sent per target. Targets that are not
+    /// neighbours are logged and skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to send.
+    /// * `targets` - The ids of the neighbours that must receive the packet.
+    ///
+    /// # Returns
+    ///
+    /// `CommonError::ControllerUnreachable` if the simulation controller is unreachable, in which
+    /// case the remaining targets are skipped.
+    fn multicast_packet(&mut self, packet: Packet, targets: &[NodeId]) -> Result<(), CommonError> {
+        for &node_id in targets {
+            if !self.packet_send_ref().contains_key(&node_id) {
+                log_error!(
+                    self.get_id(),
+                    "Error! The current node {} has no neighbour node {}.",
+                    self.get_id(),
+                    node_id
+                );
+                continue;
+            }
+            let mut packet_to_send = packet.clone();
+            packet_to_send.routing_header = build_routing_header(vec![self.get_id(), node_id]);
+            match self.forward_packet(packet_to_send) {
+                Err(e @ CommonError::ControllerUnreachable) => return Err(e),
+                Err(e) => log_error!(self.get_id(), "Failed to send packet to NodeId {:?}: {:?}", node_id, e),
+                Ok(()) => {}
+            }
+        }
+        Ok(())
+    }
+    
+    /// Reverses the routing direction of the provided packet, see `reverse_routing_header`.
+    ///
+    /// The new header points at this node, ready for `forward_packet`.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet whose routing header is to be reversed.
+    fn reverse_packet_routing_direction(&self, packet: &mut Packet) {
+        packet.routing_header = reverse_routing_header(&packet.routing_header);
+    }
+    
+    /// Builds the routing header of a packet from this node to `dest`, using the shortest
+    /// route known in `topology`.
+    ///
+    /// # Arguments
+    ///
+    /// * `topology` - The network topology known by this node.
+    /// * `dest` - The destination of the packet.
+    ///
+    /// # Returns
+    ///
+    /// The routing header, or `None` if `dest` cannot be reached.
+    fn routing_header_to(&self, topology: &TopologyGraph, dest: NodeId) -> Option<SourceRoutingHeader> {
+        topology
+            .shortest_path(self.get_id(), dest)
+            .map(build_routing_header)
+    }
+    
+    /// Adds a communication channel for a neighbouring node.
+    ///
+    /// An existing channel towards `id` is replaced, and the replacement is logged as an error
+    /// since it usually reveals the same edge being added twice; see `try_add_channel`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The node ID of the neighbour.
+    /// * `sender` - The sender channel associated with the neighbour.
+    ///
+    /// # Returns
+    ///
+    /// The sender previously registered for `id`, if any, which is replaced by `sender`.
+    fn add_channel(&mut self, id: NodeId, sender: Sender<Packet>) -> Option<Sender<Packet>> {
+        let replaced = self.get_packet_send().insert(id, sender);
+        if replaced.is_some() {
+            log_error!(
+                self.get_id(),
+                "Error! The channel of node {} towards neighbour {} was replaced.",
+                self.get_id(),
+                id
+            );
+        }
+        replaced
+    }
+    
+    /// Adds a communication channel for a neighbouring node, refusing to replace an existing one.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The node ID of the neighbour.
+    /// * `sender` - The sender channel associated with the neighbour.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the channel was added, or `CommonError::AlreadyNeighbor` if `id`
+    /// already had a channel, which is left unchanged.
+    fn try_add_channel(&mut self, id: NodeId, sender: Sender<Packet>) -> Result<(), CommonError> {
+        let packet_send = self.get_packet_send();
+        if packet_send.contains_key(&id) {
+            return Err(CommonError::AlreadyNeighbor(id));
+        }
+        packet_send.insert(id, sender);
+        Ok(())
+    }
+    
+    /// Removes the communication channel associated with a neighbouring node.
+    ///
+    /// Logs an error if no such channel exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The node ID of the neighbour to remove.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a channel was removed, `false` if `id` was not a neighbour.
+    fn remove_channel(&mut self, id: NodeId) -> bool {
+        if self.get_packet_send().remove(&id).is_none() {
+            log_error!(
+                self.get_id(),
+                "Error! The current node {} has no neighbour node {}.",
+                self.get_id(),
+                id
+            );
+            return false;
+        }
+        true
+    }
+    
+    /// Returns the number of neighbours of this node, i.e. its degree in the network.
+    fn neighbor_count(&self) -> usize {
+        self.packet_send_ref().len()
+    }
+    
+    /// Returns the ids of the neighbours of this node, sorted.
+    fn neighbors(&self) -> Vec<NodeId> {
+        let mut neighbors: Vec<NodeId> = self.packet_send_ref().keys().copied().collect();
+        neighbors.sort_unstable();
+        neighbors
+    }
+}
+
+/// A packet or a command received by the event loop of a node.
+enum Received {
+    Packet(Packet),
+    Command(Command),
+}
+
+/// Waits for the first packet or command received by a node.
+///
+/// # Arguments
+///
+/// * `node` - The node, whose packet and command receivers are selected.
+/// * `timeout` - How long to wait, or `None` to wait until something arrives.
+///
+/// # Returns
+///
+/// `None` if the timeout elapsed, otherwise what was received, or an error if the channel that
+/// became ready is disconnected.
+fn receive_next<N: NetworkNode + ?Sized>(
+    node: &N,
+    timeout: Option<Duration>,
+) -> Option<Result<Received, RecvError>> {
+    let mut select = Select::new();
+    let packet_index = select.recv(node.get_packet_receiver());
+    if let Some(command_recv) = node.get_command_receiver() {
+        select.recv(command_recv);
+    }
+    let operation = match timeout {
+        Some(timeout) => select.select_timeout(timeout).ok()?,
+        None => select.select(),
+    };
+    if operation.index() == packet_index {
+        Some(operation.recv(node.get_packet_receiver()).map(Received::Packet))
+    } else {
+        let command_recv = node
+            .get_command_receiver()
+            .expect("Failed to get the command receiver");
+        Some(operation.recv(command_recv).map(Received::Command))
+    }
+}
+
+/// Passes a received packet or command to the node, see `NetworkNode::run_once`.
+fn handle_received<N: NetworkNode + ?Sized>(
+    node: &mut N,
+    received: Option<Result<Received, RecvError>>,
+) -> RunOutcome {
+    match received {
+        None => RunOutcome::TimedOut,
+        Some(Err(_)) => RunOutcome::Disconnected,
+        Some(Ok(Received::Packet(packet))) => {
+            node.handle_packet(packet);
+            RunOutcome::Handled
+        }
+        Some(Ok(Received::Command(Command::Shutdown))) => {
+            node.dispatch_command(Command::Shutdown);
+            RunOutcome::Shutdown
+        }
+        Some(Ok(Received::Command(command))) => {
+            node.dispatch_command(command);
+            RunOutcome::Handled
+        }
+    }
+}
+
+/// Sends a `PacketSent` event to the simulation controller, then the packet to its next hop.
+///
+/// The sending half of `NetworkNode::forward_packet`, shared with
+/// `NetworkNode::broadcast_packet_parallel`: it only needs shared references, so it can run
+/// from several threads at once.
+///
+/// # Arguments
+///
+/// * `id` - The id of the sending node.
+/// * `sim_contr_send` - The channel towards the simulation controller.
+/// * `next_hop_id` - The id of the next hop.
+/// * `sender` - The channel towards the next hop.
+/// * `packet` - The packet, whose routing header already points at the next hop.
+///
+/// # Returns
+///
+/// `CommonError::ControllerUnreachable` if the event could not be sent, in which case the
+/// packet is not sent either, or `CommonError::ChannelClosed` if the next hop is gone.
+fn send_to_next_hop(
+    id: NodeId,
+    sim_contr_send: &Sender<DroneEvent>,
+    next_hop_id: NodeId,
+    sender: &Sender<Packet>,
+    packet: Packet,
+) -> Result<(), CommonError> {
+    if let Err(e) = sim_contr_send.send(DroneEvent::PacketSent(packet.clone())) {
+        log_error!(id, "Failed to send {:?} to the simulation controller", e.0);
+        return Err(CommonError::ControllerUnreachable);
+    }
+    sender
+        .send(packet)
+        .map_err(|_| CommonError::ChannelClosed(next_hop_id))
+}
+
+/// Builds the routing header of a packet about to be sent by the first node of `hops`.
+///
+/// The hop index is set to 0, i.e. the sending node itself, as `forward_packet` expects:
+/// it moves the index to the next hop when sending the packet.
+///
+/// # Arguments
+///
+/// * `hops` - The route, starting with the sending node.
+///
+/// # Panics
+///
+/// Panics if `hops` is empty.
+pub fn build_routing_header(hops: Vec<NodeId>) -> SourceRoutingHeader {
+    assert!(!hops.is_empty(), "Error! Attempt to build a routing header from an empty route");
+    SourceRoutingHeader { hop_index: 0, hops }
+}
+
+/// Returns the hops a packet still has to visit after the current one, `hops[hop_index]`.
+///
+/// # Arguments
+///
+/// * `header` - The routing header of the packet.
+///
+/// # Returns
+///
+/// The remaining hops, empty if the current hop is the last one or the hop index is past
+/// the end of the route.
+pub fn remaining_hops(header: &SourceRoutingHeader) -> &[NodeId] {
+    header
+        .hops
+        .get(header.hop_index.saturating_add(1)..)
+        .unwrap_or(&[])
+}
+
+/// Returns the hop a packet must be sent to by its current hop, `hops[hop_index]`.
+///
+/// # Arguments
+///
+/// * `header` - The routing header of the packet.
+///
+/// # Returns
+///
+/// The next hop, or `None` if the current hop is the last one or the hop index is past
+/// the end of the route.
+pub fn next_hop(header: &SourceRoutingHeader) -> Option<NodeId> {
+    remaining_hops(header).first().copied()
+}
+
+/// Checks that a route can be followed: it has at least one hop and no node appears twice.
+///
+/// # Arguments
+///
+/// * `header` - The routing header to check.
+///
+/// # Returns
+///
+/// `CommonError::EmptyRoute` if the route has no hop, or `CommonError::InvalidRoute` with the
+/// first repeated node id if the route loops.
+pub fn validate_route(header: &SourceRoutingHeader) -> Result<(), CommonError> {
+    if header.hops.is_empty() {
+        return Err(CommonError::EmptyRoute);
+    }
+    // Routes are short: a quadratic scan avoids allocating a set for every forwarded packet
+    for (position, node_id) in header.hops.iter().enumerate() {
+        if header.hops[..position].contains(node_id) {
+            return Err(CommonError::InvalidRoute(*node_id));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the header sending a packet back along the route it came from.
+///
+/// The hops beyond the current one are removed and the others are reversed, so that the
+/// new header starts at the current hop with a hop index of 0. If the current hop is the last
+/// one or the hop index is past the end of the route, nothing is removed; an empty route
+/// stays empty.
+///
+/// # Arguments
+///
+/// * `header` - The routing header of the received packet.
+pub fn reverse_routing_header(header: &SourceRoutingHeader) -> SourceRoutingHeader {
+    // Only copy the hops up to the current one (all of them if the index is past the end)
+    let end = header.hop_index.saturating_add(1).min(header.hops.len());
+    let mut hops: Vec<NodeId> = header.hops[..end].to_vec();
+    
+    // Reverse the order to set up the return path
+    hops.reverse();
+    
+    SourceRoutingHeader {
+        hop_index: 0, // Start from the current node
+        hops,
+    }
+}
+
+/// Returns the fragment index of a fragment packet, or 0 for any other packet type.
+fn fragment_index_of(packet: &Packet) -> u64 {
+    match &packet.pack_type {
+        PacketType::MsgFragment(fragment) => fragment.fragment_index,
+        _ => 0,
+    }
+}
+
+/// Returns the name of a packet type, recorded in the tracing spans of packet handling.
+#[cfg(feature = "tracing")]
+fn packet_type_name(pack_type: &PacketType) -> &'static str {
+    match pack_type {
+        PacketType::MsgFragment(_) => "MsgFragment",
+        PacketType::Ack(_) => "Ack",
+        PacketType::Nack(_) => "Nack",
+        PacketType::FloodRequest(_) => "FloodRequest",
+        PacketType::FloodResponse(_) => "FloodResponse",
+    }
+}
+
+/// Number of data bytes carried by a single message fragment, the size of `Fragment::data`
+/// in wg_2024. Smaller fragments can be produced with `fragment_message_with_size`.
+pub const FRAGMENT_SIZE: usize = 128;
+
+/// Maximum size in bytes of an encoded message accepted by `into_fragments`.
+///
+/// Larger messages would be split into thousands of fragments and flood the network.
+pub const MAX_MESSAGE_BYTES: usize = 1 << 20;
+
+/// Maximum number of fragments of a message, i.e. of a message of `MAX_MESSAGE_BYTES`.
+///
+/// `FragmentReassembler` ignores the fragments claiming a larger `total_n_fragments`.
+pub const MAX_FRAGMENTS: usize = MAX_MESSAGE_BYTES.div_ceil(FRAGMENT_SIZE);
+
+/// Default of `NetworkNode::get_max_forwards`.
+///
+/// A message of `MAX_MESSAGE_BYTES` is 8192 fragments: this leaves room for the Ack of each
+/// fragment and for several retransmissions, while still stopping a looping packet.
+pub const DEFAULT_MAX_FORWARDS: u32 = 1 << 16;
+
+/// Splits message bytes into `MsgFragment` packets of `FRAGMENT_SIZE` bytes.
+///
+/// The last fragment is zero-padded and its `length` is set to the number of meaningful bytes.
+/// An empty message yields a single empty fragment. The packets have an empty routing header:
+/// the caller is responsible for setting the route before sending them.
+///
+/// # Arguments
+///
+/// * `data` - The bytes of the message, e.g. a serialized `SerializableMessage`.
+/// * `session_id` - The session id given to every packet.
+///
+/// # Returns
+///
+/// The fragment packets, ordered by fragment index.
+pub fn fragment_message(data: &[u8], session_id: u64) -> Vec<Packet> {
+    fragment_message_with_size(data, session_id, FRAGMENT_SIZE)
+}
+
+/// Splits message bytes into `MsgFragment` packets carrying at most `fragment_size` bytes each.
+///
+/// Same as `fragment_message` with a custom fragment size; every fragment but the last carries
+/// exactly `fragment_size` meaningful bytes, the rest of its data being zero-padded.
+///
+/// # Arguments
+///
+/// * `data` - The bytes of the message.
+/// * `session_id` - The session id given to every packet.
+/// * `fragment_size` - The number of data bytes per fragment.
+///
+/// # Returns
+///
+/// The fragment packets, ordered by fragment index.
+///
+/// # Panics
+///
+/// Panics if `fragment_size` is 0 or greater than `FRAGMENT_SIZE`.
+pub fn fragment_message_with_size(data: &[u8], session_id: u64, fragment_size: usize) -> Vec<Packet> {
+    assert!(
+        fragment_size > 0 && fragment_size <= FRAGMENT_SIZE,
+        "Error! The fragment size must be between 1 and {}",
+        FRAGMENT_SIZE
+    );
+    let total_n_fragments = data.len().div_ceil(fragment_size).max(1) as u64;
+    let mut chunks: Vec<&[u8]> = data.chunks(fragment_size).collect();
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+    
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut fragment_data = [0u8; FRAGMENT_SIZE];
+            fragment_data[..chunk.len()].copy_from_slice(chunk);
+            Packet {
+                pack_type: PacketType::MsgFragment(Fragment {
+                    fragment_index: index as u64,
+                    total_n_fragments,
+                    length: chunk.len() as u8,
+                    data: fragment_data,
+                }),
+                routing_header: SourceRoutingHeader {
+                    hop_index: 0,
+                    hops: Vec::new(),
+                },
+                session_id,
+            }
+        })
+        .collect()
+}
+
+/// Splits message bytes into `MsgFragment` packets, refusing messages longer than `max_bytes`.
+///
+/// # Arguments
+///
+/// * `data` - The bytes of the message.
+/// * `session_id` - The session id given to every packet.
+/// * `max_bytes` - The maximum accepted length of `data`.
+///
+/// # Returns
+///
+/// The fragment packets as returned by `fragment_message`, or `CommonError::MessageTooLarge`
+/// if `data` is longer than `max_bytes`.
+pub fn fragment_message_with_limit(
+    data: &[u8],
+    session_id: u64,
+    max_bytes: usize,
+) -> Result<Vec<Packet>, CommonError> {
+    if data.len() > max_bytes {
+        return Err(CommonError::MessageTooLarge {
+            size: data.len(),
+            max: max_bytes,
+        });
+    }
+    Ok(fragment_message(data, session_id))
+}
+
+/// Keepalive sent by a node to the simulation controller, which flags the nodes it has not
+/// heard from for a while as dead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// The id of the node.
+    pub node_id: NodeId,
+    /// Time elapsed since the node started.
+    pub uptime: Duration,
+}
+
+/// Sends the heartbeats of a node to the simulation controller, at most once per interval
+/// unless explicitly requested.
+pub struct HeartbeatEmitter {
+    sender: Sender<Heartbeat>,
+    started: Instant,
+    interval: Duration,
+    last_beat: Option<Instant>,
+}
+
+impl HeartbeatEmitter {
+    /// Creates an emitter for a node starting now.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - The channel towards the simulation controller.
+    /// * `interval` - The minimum time between two heartbeats sent by `beat_if_due`.
+    pub fn new(sender: Sender<Heartbeat>, interval: Duration) -> Self {
+        Self {
+            sender,
+            started: Instant::now(),
+            interval,
+            last_beat: None,
+        }
+    }
+    
+    /// Sends a heartbeat now. A closed channel is logged and otherwise ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The id of the node sending the heartbeat.
+    pub fn beat(&mut self, node_id: NodeId) {
+        let now = Instant::now();
+        self.last_beat = Some(now);
+        let heartbeat = Heartbeat {
+            node_id,
+            uptime: now.duration_since(self.started),
+        };
+        if self.sender.send(heartbeat).is_err() {
+            log_error!(node_id, "Failed to send the heartbeat");
+        }
+    }
+    
+    /// Sends a heartbeat if none was sent during the last interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The id of the node sending the heartbeat.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a heartbeat was sent.
+    pub fn beat_if_due(&mut self, node_id: NodeId) -> bool {
+        let is_due = self
+            .last_beat
+            .is_none_or(|last_beat| last_beat.elapsed() >= self.interval);
+        if is_due {
+            self.beat(node_id);
+        }
+        is_due
+    }
+}
+
+/// Tells when a flood initiated by a node has settled, i.e. when no new node has answered it
+/// for a while.
+///
+/// Each flood response adds the node that ended the flood, the last one of its path trace, to
+/// the responders. The flood is complete once `timeout` has elapsed since the last new responder
+/// (or since the flood started, if nobody answered): the topology discovered so far can then be
+/// used for routing.
+pub struct FloodTracker {
+    flood_id: u64,
+    timeout: Duration,
+    last_change: Instant,
+    responders: HashSet<NodeId>,
+}
+
+impl FloodTracker {
+    /// Creates a tracker for a flood starting now.
+    ///
+    /// # Arguments
+    ///
+    /// * `flood_id` - The id of the tracked flood.
+    /// * `timeout` - How long without a new responder before the flood is complete.
+    pub fn new(flood_id: u64, timeout: Duration) -> Self {
+        Self {
+            flood_id,
+            timeout,
+            last_change: Instant::now(),
+            responders: HashSet::new(),
+        }
+    }
+    
+    /// Tracks a new flood starting now, forgetting the responders of the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `flood_id` - The id of the new flood.
+    pub fn restart(&mut self, flood_id: u64) {
+        *self = Self::new(flood_id, self.timeout);
+    }
+    
+    /// Returns the id of the tracked flood.
+    pub fn flood_id(&self) -> u64 {
+        self.flood_id
+    }
+    
+    /// Records a received flood response.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The received flood response. Responses to other floods are ignored.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the response came from a node that had not answered the flood yet.
+    pub fn record(&mut self, response: &FloodResponse) -> bool {
+        if response.flood_id != self.flood_id {
+            return false;
+        }
+        let Some(&(responder, _)) = response.path_trace.last() else {
+            return false;
+        };
+        let is_new = self.responders.insert(responder);
+        if is_new {
+            self.last_change = Instant::now();
+        }
+        is_new
+    }
+    
+    /// Returns the nodes that answered the flood so far.
+    pub fn responders(&self) -> &HashSet<NodeId> {
+        &self.responders
+    }
+    
+    /// Returns whether the flood has settled, see `FloodTracker`.
+    pub fn is_complete(&self) -> bool {
+        self.is_complete_at(Instant::now())
+    }
+    
+    /// Returns whether the flood has settled at a given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The time at which completion is checked.
+    pub fn is_complete_at(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_change) >= self.timeout
+    }
+}
+
+/// Fragments sent by a node and kept until they are acknowledged, to be retransmitted if dropped.
+#[derive(Default)]
+pub struct SentFragmentBuffer {
+    packets: HashMap<(u64, u64), Packet>,
+}
+
+impl SentFragmentBuffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Keeps a sent fragment packet until it is acknowledged.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The sent packet, including the routing header it was sent with.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the packet is not a fragment packet and was therefore not recorded.
+    pub fn record(&mut self, packet: Packet) -> bool {
+        match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => {
+                self.packets
+                    .insert((packet.session_id, fragment.fragment_index), packet);
+                true
+            }
+            _ => false,
+        }
+    }
+    
+    /// Removes an acknowledged fragment from the buffer.
+    ///
+    /// # Returns
+    ///
+    /// The acknowledged packet, or `None` if it was not in the buffer.
+    pub fn ack(&mut self, session_id: u64, fragment_index: u64) -> Option<Packet> {
+        self.packets.remove(&(session_id, fragment_index))
+    }
+    
+    /// Returns the sent packet of a fragment not yet acknowledged, if any.
+    pub fn get(&self, session_id: u64, fragment_index: u64) -> Option<&Packet> {
+        self.packets.get(&(session_id, fragment_index))
+    }
+    
+    /// Returns the packets of a session not yet acknowledged, ordered by fragment index.
+    pub fn pending_for(&self, session_id: u64) -> Vec<Packet> {
+        let mut pending: Vec<(u64, &Packet)> = self
+            .packets
+            .iter()
+            .filter(|((session, _), _)| *session == session_id)
+            .map(|((_, fragment_index), packet)| (*fragment_index, packet))
+            .collect();
+        pending.sort_by_key(|(fragment_index, _)| *fragment_index);
+        pending.into_iter().map(|(_, packet)| packet.clone()).collect()
+    }
+}
+
+/// Fragments received so far for a single session.
+struct PartialMessage {
+    /// Data of each fragment, indexed by fragment index; `None` until the fragment arrives.
+    fragments: Vec<Option<Vec<u8>>>,
+    /// Number of fragments still missing.
+    missing_count: usize,
+}
+
+/// Reassembles message fragments into the original bytes, one message per session id.
+///
+/// Fragments can arrive in any order; duplicates are ignored. At most `capacity` sessions are
+/// reassembled at once: starting a new session when full abandons the oldest incomplete one,
+/// so that the memory used by a node stays bounded even if senders never finish their messages.
+pub struct FragmentReassembler {
+    capacity: usize,
+    sessions: HashMap<u64, PartialMessage>,
+    order: VecDeque<u64>,
+}
+
+impl FragmentReassembler {
+    /// Capacity used by `FragmentReassembler::new`.
+    pub const DEFAULT_CAPACITY: usize = 64;
+    
+    /// Creates an empty reassembler, reassembling up to `DEFAULT_CAPACITY` sessions at once.
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+    
+    /// Creates an empty reassembler, reassembling up to `capacity` sessions at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "FragmentReassembler capacity must be positive");
+        Self {
+            capacity,
+            sessions: HashMap::new(),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+    
+    /// Stores a received fragment.
+    ///
+    /// A fragment whose index is out of range, whose `total_n_fragments` is above
+    /// `MAX_FRAGMENTS`, or whose `total_n_fragments` differs from the previous fragments of the
+    /// same session, is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session the fragment belongs to.
+    /// * `fragment` - The received fragment.
+    ///
+    /// # Returns
+    ///
+    /// The reassembled message bytes once every fragment of the session has arrived
+    /// (the session is then forgotten), `None` otherwise.
+    pub fn insert(&mut self, session_id: u64, fragment: Fragment) -> Option<Vec<u8>> {
+        // Checked before any allocation: the field comes from the network
+        if fragment.total_n_fragments > MAX_FRAGMENTS as u64 {
+            return None;
+        }
+        let total = fragment.total_n_fragments as usize;
+        let index = fragment.fragment_index as usize;
+        if index >= total {
+            return None;
+        }
+        
+        if !self.sessions.contains_key(&session_id) {
+            if self.order.len() == self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.sessions.remove(&oldest);
+                }
+            }
+            self.order.push_back(session_id);
+        }
+        let partial = self.sessions.entry(session_id).or_insert_with(|| PartialMessage {
+            fragments: vec![None; total],
+            missing_count: total,
+        });
+        if partial.fragments.len() != total || partial.fragments[index].is_some() {
+            return None;
+        }
+        
+        let length = (fragment.length as usize).min(FRAGMENT_SIZE);
+        partial.fragments[index] = Some(fragment.data[..length].to_vec());
+        partial.missing_count -= 1;
+        if partial.missing_count > 0 {
+            return None;
+        }
+        
+        self.order.retain(|&pending| pending != session_id);
+        let partial = self.sessions.remove(&session_id)?;
+        Some(partial.fragments.into_iter().flatten().flatten().collect())
+    }
+    
+    /// Returns the indices of the fragments not yet received for a session,
+    /// or an empty vector if no fragment of the session is pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - The session to inspect.
+    pub fn missing(&self, session_id: u64) -> Vec<u64> {
+        self.sessions
+            .get(&session_id)
+            .map(|partial| {
+                partial
+                    .fragments
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, data)| data.is_none())
+                    .map(|(index, _)| index as u64)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Default for FragmentReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------------
+// ----------------------------------- TESTS ------------------------------------------------------------
+// ------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashSet;
+    use crate::seen_flood_cache::FloodKey;
+    
+    struct TestNode {
+        id: NodeId,
+        crashing: bool,
+        pdr: f32,
+        seen_flood_ids: HashSet<FloodKey>,
+        senders: HashMap<NodeId, Sender<Packet>>,
+        receiver: Receiver<Packet>,
+        rng: StdRng,
+        sim_controller: Sender<DroneEvent>,
+        /// Keeps the simulation controller channel open.
+        _sim_receiver: Receiver<DroneEvent>,
+        metrics: NodeMetrics,
+        sent_fragments: SentFragmentBuffer,
+        seen_fragments: SeenFragmentCache,
+        heartbeat: Option<HeartbeatEmitter>,
+        commands: Option<Receiver<Command>>,
+        shutting_down: bool,
+        last_flood_id: u64,
+        recorder: Option<PacketRecorder>,
+        drop_records: Option<Sender<DropRecord>>,
+        flood_tracker: Option<FloodTracker>,
+        blocklist: HashSet<NodeId>,
+        forward_counts: Option<ForwardCounts>,
+        max_forwards: u32,
+        route_cache: Option<RouteCache>,
+        flood_responses: Vec<FloodResponse>,
+    }
+    
+    impl NetworkNode for TestNode {
+        /// Returns the unique identifier for this test node.
+        fn get_id(&self) -> NodeId {
+            self.id
+        }
+        
+        /// Test nodes behave as drones.
+        fn get_node_type(&self) -> NodeType {
+            NodeType::Drone
+        }
+        
+        /// Returns whether this test node is set to crash.
+        fn get_crashing_behavior(&self) -> bool {
+            self.crashing
+        }
+        
+        /// Returns the packet drop rate of this test node.
+        fn get_pdr(&self) -> f32 {
+            self.pdr
+        }
+        
+        /// Provides mutable access to the set of flood request IDs seen by this test node.
+        fn get_seen_flood_ids(&mut self) -> &mut dyn SeenFloodIds {
+            &mut self.seen_flood_ids
+        }
+        
+        /// Returns a mutable reference to the mapping of neighbour nodes to their sender channels.
+        fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>> {
+            &mut self.senders
+        }
+        
+        /// Returns a reference to the mapping of neighbour nodes to their sender channels.
+        fn packet_send_ref(&self) -> &HashMap<NodeId, Sender<Packet>> {
+            &self.senders
+        }
+        
+        /// Retrieves a reference to the receiver channel for incoming packets.
+        fn get_packet_receiver(&self) -> &Receiver<Packet> {
+            &self.receiver
+        }
+        
+        /// Returns the command receiver of this test node, if one was set.
+        fn get_command_receiver(&self) -> Option<&Receiver<Command>> {
+            self.commands.as_ref()
+        }
+        
+        /// Returns a mutable reference to the test node's random number generator.
+        fn get_random_generator(&mut self) -> &mut dyn RngCore {
+            &mut self.rng
+        }
+        
+        /// Returns a reference to the simulation controller sender channel.
+        fn get_sim_contr_send(&self) -> &Sender<DroneEvent> {
+            &self.sim_controller
+        }
+        
+        /// Returns a mutable reference to the test node's traffic counters.
+        fn get_metrics(&mut self) -> &mut NodeMetrics {
+            &mut self.metrics
+        }
+        
+        /// Returns the last flood id generated by this test node.
+        fn get_last_flood_id(&mut self) -> &mut u64 {
+            &mut self.last_flood_id
+        }
+        
+        /// Returns the buffer of fragments sent by this test node.
+        fn get_sent_fragment_buffer(&mut self) -> Option<&mut SentFragmentBuffer> {
+            Some(&mut self.sent_fragments)
+        }
+        
+        /// Returns the drop record channel of this test node, if one was set.
+        fn get_drop_record_send(&self) -> Option<&Sender<DropRecord>> {
+            self.drop_records.as_ref()
+        }
+        
+        /// Returns the packet recorder of this test node, if one was set.
+        fn get_packet_recorder(&mut self) -> Option<&mut PacketRecorder> {
+            self.recorder.as_mut()
+        }
+        
+        /// Returns the neighbours blocked by this test node.
+        fn broadcast_blocklist_ref(&self) -> Option<&HashSet<NodeId>> {
+            Some(&self.blocklist)
+        }
+        
+        /// Returns a mutable reference to the neighbours blocked by this test node.
+        fn get_broadcast_blocklist(&mut self) -> Option<&mut HashSet<NodeId>> {
+            Some(&mut self.blocklist)
+        }
+        
+        /// Returns the forward limit of this test node.
+        fn get_max_forwards(&self) -> u32 {
+            self.max_forwards
+        }
+        
+        /// Returns the forward counts of this test node, if they were set.
+        fn get_forward_counts(&mut self) -> Option<&mut ForwardCounts> {
+            self.forward_counts.as_mut()
+        }
+        
+        /// Returns the route cache of this test node, if one was set.
+        fn get_route_cache(&mut self) -> Option<&mut RouteCache> {
+            self.route_cache.as_mut()
+        }
+        
+        /// Returns the flood tracker of this test node, if one was set.
+        fn get_flood_tracker(&mut self) -> Option<&mut FloodTracker> {
+            self.flood_tracker.as_mut()
+        }
+        
+        /// Returns the set of fragments processed by this test node.
+        fn get_seen_fragments(&mut self) -> Option<&mut SeenFragmentCache> {
+            Some(&mut self.seen_fragments)
+        }
+        
+        /// Returns the heartbeat emitter of this test node, if one was set.
+        fn get_heartbeat_emitter(&mut self) -> Option<&mut HeartbeatEmitter> {
+            self.heartbeat.as_mut()
+        }
+        
+        /// Records the flood response, see `test_flood_response_hook`.
+        fn handle_flood_response(&mut self, resp: FloodResponse, _packet: Packet) -> bool {
+            self.flood_responses.push(resp);
+            true
+        }
+        
+        /// Test implementation for handling a routed packet.
+        /// This function is unimplemented in the test node.
+        fn handle_routed_packet(&mut self, _packet: Packet) -> bool {
+            unimplemented!()
+        }
+        
+        /// Returns whether this test node received a `Command::Shutdown`.
+        fn is_shutting_down(&self) -> bool {
+            self.shutting_down
+        }
+        
+        /// Test implementation for handling a command.
+        /// Only `Command::Shutdown` is supported by the test node.
+        fn handle_command(&mut self, command: Command) {
+            match command {
+                Command::Shutdown => self.shutting_down = true,
+                _ => unimplemented!(),
+            }
+        }
+        
+        /// Test implementation for executing a command: only removing a sender is supported,
+        /// and fails if the sender does not exist.
+        fn try_handle_command(&mut self, command: Command) -> Result<(), CommonError> {
+            match command {
+                Command::Client(ClientCommand::RemoveSender(id)) => {
+                    if self.senders.remove(&id).is_none() {
+                        return Err(CommonError::NextHopUnreachable(id));
+                    }
+                    Ok(())
+                }
+                _ => unimplemented!(),
+            }
+        }
+    }
+    
+    impl TestNode {
+        /// Creates a new test node with the specified identifier.
+        fn new(id: NodeId) -> Self {
+            let (sim_controller, _sim_receiver) = unbounded();
+            Self {
+                id,
+                crashing: false,
+                pdr: 0.0,
+                seen_flood_ids: HashSet::new(),
+                senders: HashMap::new(),
+                receiver: unbounded().1,
+                rng: StdRng::from_entropy(),
+                sim_controller,
+                _sim_receiver,
+                metrics: NodeMetrics::default(),
+                sent_fragments: SentFragmentBuffer::new(),
+                seen_fragments: SeenFragmentCache::new(),
+                heartbeat: None,
+                commands: None,
+                shutting_down: false,
+                last_flood_id: 0,
+                recorder: None,
+                drop_records: None,
+                flood_tracker: None,
+                blocklist: HashSet::new(),
+                forward_counts: None,
+                max_forwards: DEFAULT_MAX_FORWARDS,
+                route_cache: None,
+                flood_responses: Vec::new(),
+            }
+        }
+    }
+    
+    /// Tests the `forward_packet` function to ensure that a packet is correctly forwarded to the next hop
+    /// and that a simulation event is generated.
+    #[test]
+    fn test_forward_packet() {
+        // Create a test node with ID 1
+        let mut node = TestNode::new(1);
+        
+        // Set up communication channel to node 2
+        let (sender, receiver) = unbounded();
+        let (sim_sender, sim_receiver) = unbounded();
+        node.senders.insert(2, sender);
+        node.sim_controller = sim_sender;
+        
+        // Create a test packet with routing header [1, 2] where the current hop is at index 0.
+        let packet = Packet {
+            pack_type: wg_2024::packet::PacketType::Ack(wg_2024::packet::Ack { fragment_index: 0 }),
+            routing_header: wg_2024::network::SourceRoutingHeader {
+                hop_index: 0,
+                hops: vec![1, 2],
+            },
+            session_id: 42,
+        };
+        
+        // Test forwarding the packet from node 1 to node 2
+        node.forward_packet(packet.clone()).expect("Failed to forward packet");
+        
+        // Verify the packet was received by node 2
+        let received = receiver.try_recv().expect("Failed to receive packet");
+        assert_eq!(received.session_id, 42);
+        
+        // Verify that a simulation event was generated
+        let sim_event = sim_receiver.try_recv().expect("Failed to receive simulation controller event");
+        match sim_event {
+            DroneEvent::PacketSent(p) => assert_eq!(p.session_id, 42),
+            _ => panic!("Expected PacketSent event"),
+        }
+        
+        // Verify that the forwarded packet was counted
+        assert_eq!(node.metrics.forwarded, 1);
+    }
+    
+    /// Tests that forwarding 10k packets in a loop on a node with many neighbours delivers all
+    /// of them, in order, to the next hop. That the cost per packet does not grow with the
+    /// number of neighbours is measured by the `forward` benchmark, not by this test.
+    #[test]
+    fn test_forward_packet_loop() {
+        let mut node = TestNode::new(1);
+        let (sender, receiver) = unbounded();
+        let (sim_sender, _sim_receiver) = unbounded();
+        node.senders.insert(2, sender);
+        node.sim_controller = sim_sender;
+        for neighbour in 3..20 {
+            node.senders.insert(neighbour, unbounded().0);
+        }
+        
+        for session_id in 0..10_000 {
+            let packet = Packet {
+                pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+                routing_header: SourceRoutingHeader {
+                    hop_index: 0,
+                    hops: vec![1, 2],
+                },
+                session_id,
+            };
+            node.forward_packet(packet).expect("Failed to forward packet");
+        }
+        
+        assert_eq!(node.metrics.forwarded, 10_000);
+        for session_id in 0..10_000 {
+            assert_eq!(receiver.try_recv().expect("Failed to receive packet").session_id, session_id);
+        }
+    }
+    
+    /// Tests that `forward_packet` reports an unknown next hop and a closed channel as errors
+    /// instead of panicking.
+    #[test]
+    fn test_forward_packet_errors() {
+        let mut node = TestNode::new(1);
+        let packet = Packet {
+            pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+            routing_header: SourceRoutingHeader {
+                hop_index: 0,
+                hops: vec![1, 2],
+            },
+            session_id: 42,
+        };
+        
+        // No channel towards node 2
+        match node.forward_packet(packet.clone()) {
+            Err(CommonError::NextHopUnreachable(2)) => {}
+            other => panic!("Expected NextHopUnreachable error, got {:?}", other),
+        }
+        
+        // Channel towards node 2 exists but its receiver was dropped
+        let (sender, receiver) = unbounded();
+        drop(receiver);
+        node.senders.insert(2, sender);
+        match node.forward_packet(packet) {
+            Err(CommonError::ChannelClosed(2)) => {}
+            other => panic!("Expected ChannelClosed error, got {:?}", other),
+        }
+    }
+    
+    /// Tests that reversing a route whose current hop is the last one keeps every hop.
+    #[test]
+    fn test_reverse_routing_at_last_hop() {
+        let node = TestNode::new(3);
+        let mut packet = Packet {
+            pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+            routing_header: SourceRoutingHeader {
+                hop_index: 2,
+                hops: vec![1, 2, 3],
+            },
+            session_id: 42,
+        };
+        
+        node.reverse_packet_routing_direction(&mut packet);
+        
+        assert_eq!(packet.routing_header.hops, vec![3, 2, 1]);
+        assert_eq!(packet.routing_header.hop_index, 0);
+    }
+    
+    /// Tests that reversing an empty route does not panic.
+    #[test]
+    fn test_reverse_routing_empty_hops() {
+        let node = TestNode::new(1);
+        let mut packet = Packet {
+            pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+            routing_header: SourceRoutingHeader {
+                hop_index: 0,
+                hops: vec![],
+            },
+            session_id: 42,
+        };
+        
+        node.reverse_packet_routing_direction(&mut packet);
+        
+        assert!(packet.routing_header.hops.is_empty());
+    }
+    
+    /// Tests that `handle_packet` hands a flood response to `handle_flood_response`, not to
+    /// `handle_routed_packet`.
+    #[test]
+    fn test_flood_response_hook() {
+        let mut node = TestNode::new(2);
+        let flood_response = FloodResponse {
+            flood_id: 101,
+            path_trace: vec![(1, NodeType::Client), (2, NodeType::Drone), (3, NodeType::Server)],
+        };
+        let packet = Packet {
+            pack_type: PacketType::FloodResponse(flood_response.clone()),
+            routing_header: SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![3, 2, 1],
+            },
+            session_id: 101,
+        };
+        
+        assert!(node.handle_packet(packet));
+        assert_eq!(node.flood_responses.len(), 1);
+        assert_eq!(node.flood_responses[0].flood_id, flood_response.flood_id);
+        assert_eq!(node.flood_responses[0].path_trace, flood_response.path_trace);
+    }
+    
+    /// Tests that a crashing node drops flood requests instead of broadcasting them.
+    #[test]
+    fn test_crashing_node_drops_flood_request() {
+        let mut node = TestNode::new(1);
+        node.crashing = true;
+        
+        let (sender_2, receiver_2) = unbounded();
+        let (sender_3, receiver_3) = unbounded();
+        node.senders.insert(2, sender_2);
+        node.senders.insert(3, sender_3);
+        
+        let packet = Packet {
+            pack_type: PacketType::FloodRequest(FloodRequest {
+                flood_id: 7,
+                initiator_id: 2,
+                path_trace: vec![(2, NodeType::Client)],
+            }),
+            routing_header: SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![2, 1],
+            },
+            session_id: 7,
+        };
+        
+        let (sim_sender, sim_receiver) = unbounded();
+        node.sim_controller = sim_sender;
+        
+        assert!(!node.handle_packet(packet));
+        assert!(receiver_2.try_recv().is_err());
+        assert!(receiver_3.try_recv().is_err());
+        assert!(node.seen_flood_ids.is_empty());
+        assert_eq!(node.metrics.dropped, 1);
+        
+        // The drop is reported to the simulation controller
+        match sim_receiver.try_recv().expect("Failed to receive simulation controller event") {
+            DroneEvent::PacketDropped(p) => assert_eq!(p.session_id, 7),
+            _ => panic!("Expected PacketDropped event"),
+        }
+    }
+    
+    /// Tests that `check_recipient` accepts a packet whose current hop is the node
+    /// and rejects it with an `UnexpectedRecipient` Nack otherwise.
+    #[test]
+    fn test_check_recipient() {
+        let node = TestNode::new(2);
+        let mut packet = Packet {
+            pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+            routing_header: SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![1, 2, 3],
+            },
+            session_id: 42,
+        };
+        
+        assert!(node.check_recipient(&packet).is_ok());
+        
+        packet.routing_header.hop_index = 2;
+        let nack = node.check_recipient(&packet).expect_err("Expected an UnexpectedRecipient Nack");
+        assert!(matches!(nack.nack_type, NackType::UnexpectedRecipient(2)));
+    }
+    
+    /// Tests that a flood request whose path trace already contains the node is answered
+    /// with a flood response instead of being broadcast again.
+    #[test]
+    fn test_flood_request_loop_is_not_rebroadcast() {
+        let mut node = TestNode::new(1);
+        let (sender_2, receiver_2) = unbounded();
+        let (sender_3, receiver_3) = unbounded();
+        node.senders.insert(2, sender_2);
+        node.senders.insert(3, sender_3);
+        
+        // The request went 3 -> 1 -> 2 and came back to 1
+        let packet = Packet {
+            pack_type: PacketType::FloodRequest(FloodRequest {
+                flood_id: 7,
+                initiator_id: 3,
+                path_trace: vec![(3, NodeType::Client), (1, NodeType::Drone), (2, NodeType::Drone)],
+            }),
+            routing_header: SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![2, 1],
+            },
+            session_id: 7,
+        };
+        
+        node.handle_flood_request(packet);
+        
+        // No re-broadcast towards 2, and a flood response back to the initiator that still
+        // reports the 2 -> 1 link
+        assert!(receiver_2.try_recv().is_err());
+        let response = receiver_3.try_recv().expect("Failed to receive the flood response");
+        assert_eq!(response.routing_header.hops, vec![1, 3]);
+        match response.pack_type {
+            PacketType::FloodResponse(flood_response) => {
+                let path: Vec<NodeId> = flood_response.path_trace.iter().map(|(id, _)| *id).collect();
+                assert_eq!(path, vec![3, 1, 2, 1]);
+            }
+            _ => panic!("Expected a FloodResponse"),
+        }
+        assert!(receiver_3.try_recv().is_err());
+    }
+    
+    /// Builds a fragment carrying `bytes` for the tests.
+    fn test_fragment(fragment_index: u64, total_n_fragments: u64, bytes: &[u8]) -> Fragment {
+        let mut data = [0u8; FRAGMENT_SIZE];
+        data[..bytes.len()].copy_from_slice(bytes);
+        Fragment {
+            fragment_index,
+            total_n_fragments,
+            length: bytes.len() as u8,
+            data,
+        }
+    }
+    
+    /// Extracts the fragments of `MsgFragment` packets, keeping their order.
+    fn fragments_of(packets: Vec<Packet>) -> Vec<Fragment> {
+        packets
+            .into_iter()
+            .map(|packet| match packet.pack_type {
+                PacketType::MsgFragment(fragment) => fragment,
+                _ => panic!("Expected a MsgFragment"),
+            })
+            .collect()
+    }
+    
+    /// Tests that fragments arriving out of order are reassembled in index order.
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let mut reassembler = FragmentReassembler::new();
+        
+        assert!(reassembler.insert(5, test_fragment(2, 3, b"!")).is_none());
+        assert_eq!(reassembler.missing(5), vec![0, 1]);
+        assert!(reassembler.insert(5, test_fragment(0, 3, b"hello ")).is_none());
+        assert_eq!(reassembler.missing(5), vec![1]);
+        let message = reassembler.insert(5, test_fragment(1, 3, b"world"));
+        
+        assert_eq!(message, Some(b"hello world!".to_vec()));
+        assert!(reassembler.missing(5).is_empty());
+    }
+    
+    /// Tests that a duplicated fragment is ignored.
+    #[test]
+    fn test_reassemble_duplicate_fragment() {
+        let mut reassembler = FragmentReassembler::new();
+        
+        assert!(reassembler.insert(5, test_fragment(0, 2, b"ab")).is_none());
+        assert!(reassembler.insert(5, test_fragment(0, 2, b"xx")).is_none());
+        assert_eq!(reassembler.missing(5), vec![1]);
+        
+        assert_eq!(reassembler.insert(5, test_fragment(1, 2, b"cd")), Some(b"abcd".to_vec()));
+    }
+    
+    /// Tests that a fragment claiming more fragments than a message can have is ignored
+    /// without allocating them.
+    #[test]
+    fn test_reassemble_oversized_total() {
+        let mut reassembler = FragmentReassembler::new();
+        
+        assert!(reassembler.insert(5, test_fragment(0, u64::MAX, b"ab")).is_none());
+        assert!(reassembler.insert(6, test_fragment(0, MAX_FRAGMENTS as u64 + 1, b"ab")).is_none());
+        assert!(reassembler.missing(5).is_empty());
+        assert!(reassembler.missing(6).is_empty());
+        
+        assert!(reassembler.insert(7, test_fragment(0, MAX_FRAGMENTS as u64, b"ab")).is_none());
+        assert_eq!(reassembler.missing(7).len(), MAX_FRAGMENTS - 1);
+    }
+    
+    /// Tests that starting a session when the reassembler is full abandons the oldest one.
+    #[test]
+    fn test_reassemble_evicts_oldest_session() {
+        let mut reassembler = FragmentReassembler::with_capacity(2);
+        
+        assert!(reassembler.insert(1, test_fragment(0, 2, b"a")).is_none());
+        assert!(reassembler.insert(2, test_fragment(0, 2, b"b")).is_none());
+        assert_eq!(reassembler.insert(2, test_fragment(1, 2, b"c")), Some(b"bc".to_vec()));
+        assert!(reassembler.insert(3, test_fragment(0, 2, b"d")).is_none());
+        assert_eq!(reassembler.missing(1), vec![1]);
+        
+        assert!(reassembler.insert(4, test_fragment(0, 2, b"e")).is_none());
+        assert!(reassembler.missing(1).is_empty());
+        assert_eq!(reassembler.missing(3), vec![1]);
+        assert_eq!(reassembler.insert(3, test_fragment(1, 2, b"f")), Some(b"df".to_vec()));
+    }
+    
+    /// Tests that fragmenting a message then reassembling it yields the original bytes.
+    #[test]
+    fn test_fragment_round_trip() {
+        let data: Vec<u8> = (0..300).map(|i| (i % 251) as u8).collect();
+        let packets = fragment_message(&data, 9);
+        assert_eq!(packets.len(), 3);
+        
+        let mut reassembler = FragmentReassembler::new();
+        let mut message = None;
+        for packet in packets.into_iter().rev() {
+            assert_eq!(packet.session_id, 9);
+            match packet.pack_type {
+                PacketType::MsgFragment(fragment) => {
+                    assert_eq!(fragment.total_n_fragments, 3);
+                    if fragment.fragment_index == 2 {
+                        assert_eq!(fragment.length, 44);
+                        assert!(fragment.data[44..].iter().all(|&byte| byte == 0));
+                    }
+                    message = reassembler.insert(packet.session_id, fragment);
+                }
+                _ => panic!("Expected a MsgFragment"),
+            }
+        }
+        
+        assert_eq!(message, Some(data));
+    }
+    
+    /// Tests that a message split into fragments is rebuilt identically.
+    #[test]
+    fn test_message_fragments_round_trip() {
+        let text = "a long chat message ".repeat(20);
+        let message = SerializableMessage::Chat(1, 2, 3, text.clone());
+        
+        let packets = message
+            .into_fragments(4, Encoding::Bincode)
+            .expect("Failed to fragment the message");
+        let fragments: Vec<Fragment> = fragments_of(packets).into_iter().rev().collect();
+        assert!(fragments.len() > 1);
+        
+        match SerializableMessage::from_fragments(&fragments, Encoding::Bincode) {
+            Ok(SerializableMessage::Chat(1, 2, 3, received)) => assert_eq!(received, text),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        
+        // Dropping a fragment is reported instead of producing a garbled message
+        assert!(matches!(
+            SerializableMessage::from_fragments(&fragments[1..], Encoding::Bincode),
+            Err(CommonError::DeserializeFailed(DeserializeError::MissingFragments { .. }))
+        ));
+    }
+    
+    /// Tests that a corrupted byte in a checksummed message is reported as a checksum mismatch.
+    #[test]
+    fn test_checksum_mismatch() {
+        let message = SerializableMessage::FileFound(1, "notes.txt".to_string(), "content".repeat(30));
+        let packets = Checksummed(message)
+            .into_fragments(4, Encoding::Bincode)
+            .expect("Failed to fragment the message");
+        let mut fragments: Vec<Fragment> = fragments_of(packets);
+        assert!(matches!(
+            Checksummed::from_fragments(&fragments, Encoding::Bincode),
+            Ok(SerializableMessage::FileFound(1, _, _))
+        ));
+        
+        fragments[1].data[10] ^= 0xff;
+        assert!(matches!(
+            Checksummed::from_fragments(&fragments, Encoding::Bincode),
+            Err(CommonError::DeserializeFailed(DeserializeError::ChecksumMismatch { .. }))
+        ));
+    }
+    
+    /// Tests that `build_ack` acknowledges fragments and refuses any other packet type.
+    #[test]
+    fn test_build_ack() {
+        let node = TestNode::new(2);
+        let mut packet = fragment_message(b"data", 42).remove(0);
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2],
+        };
+        
+        let ack = node.build_ack(packet.clone()).expect("Failed to build the ack");
+        assert!(matches!(ack.pack_type, PacketType::Ack(Ack { fragment_index: 0 })));
+        assert_eq!(ack.routing_header.hops, vec![2, 1]);
+        
+        packet.pack_type = PacketType::Ack(Ack { fragment_index: 0 });
+        assert!(matches!(node.build_ack(packet), Err(CommonError::NonFragmentPacket)));
+    }
+    
+    /// Tests that a dropped fragment is retransmitted from the buffer, then evicted once acked.
+    #[test]
+    fn test_retransmit_dropped_fragment() {
+        let mut node = TestNode::new(1);
+        let (sender, receiver) = unbounded();
+        node.senders.insert(2, sender);
+        
+        let mut packets = fragment_message(&[7u8; 200], 42);
+        for packet in packets.iter_mut() {
+            packet.routing_header = SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![1, 2, 3],
+            };
+            node.sent_fragments.record(packet.clone());
+        }
+        assert_eq!(node.sent_fragments.pending_for(42).len(), 2);
+        
+        // Fragment 1 is dropped on its way and retransmitted
+        node.handle_nack(&Nack { fragment_index: 1, nack_type: NackType::Dropped }, 42);
+        let retransmitted = receiver.try_recv().expect("Failed to receive the retransmitted fragment");
+        match retransmitted.pack_type {
+            PacketType::MsgFragment(fragment) => assert_eq!(fragment.fragment_index, 1),
+            _ => panic!("Expected a MsgFragment"),
+        }
+        
+        // Both fragments are then acknowledged
+        node.handle_ack(&Ack { fragment_index: 0 }, 42);
+        node.handle_ack(&Ack { fragment_index: 1 }, 42);
+        assert!(node.sent_fragments.pending_for(42).is_empty());
+    }
+    
+    /// Tests that a node built with a fixed-seed generator generates reproducible values.
+    #[test]
+    fn test_seeded_random_generator() {
+        let mut node = TestNode::new(1);
+        node.rng = StdRng::seed_from_u64(2024);
+        let session_id: u64 = node.get_random_generator().gen();
+        
+        let expected: u64 = StdRng::seed_from_u64(2024).gen();
+        assert_eq!(session_id, expected);
+    }
+    
+    /// Tests that the observed drop rate over many fragments is close to the configured PDR,
+    /// and that control packets are never dropped.
+    #[test]
+    fn test_packet_drop_rate() {
+        let mut node = TestNode::new(1);
+        node.pdr = 0.3;
+        node.rng = StdRng::seed_from_u64(7);
+        let (sender, _receiver) = unbounded();
+        let (sim_sender, _sim_receiver) = unbounded();
+        node.senders.insert(2, sender);
+        node.sim_controller = sim_sender;
+        
+        let mut fragment = fragment_message(b"data", 42).remove(0);
+        fragment.routing_header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![2, 1, 3],
+        };
+        let dropped = (0..10_000).filter(|_| node.maybe_drop(&fragment)).count();
+        assert!((2_700..=3_300).contains(&dropped), "dropped {} packets", dropped);
+        assert_eq!(node.metrics.dropped, dropped as u64);
+        
+        let mut ack = fragment.clone();
+        ack.pack_type = PacketType::Ack(Ack { fragment_index: 0 });
+        assert!((0..1_000).all(|_| !node.maybe_drop(&ack)));
+    }
+    
+    /// Tests that `routing_header_to` builds a header following the shortest known route.
+    #[test]
+    fn test_routing_header_to() {
+        let node = TestNode::new(1);
+        let mut topology = TopologyGraph::new();
+        topology.add_flood_response(&wg_2024::packet::FloodResponse {
+            flood_id: 0,
+            path_trace: vec![(1, NodeType::Client), (2, NodeType::Drone), (3, NodeType::Server)],
+        });
+        
+        let header = node.routing_header_to(&topology, 3).expect("Failed to find a route");
+        assert_eq!(header.hop_index, 0);
+        assert_eq!(header.hops, vec![1, 2, 3]);
+        assert!(node.routing_header_to(&topology, 4).is_none());
+    }
+    
+    /// Tests that a broadcast reaches every neighbour except the one the packet came from.
+    #[test]
+    fn test_broadcast_packet_skips_sender() {
+        let mut node = TestNode::new(1);
+        let (sim_sender, sim_receiver) = unbounded();
+        node.sim_controller = sim_sender;
+        let mut receivers = HashMap::new();
+        for neighbour in 2..6 {
+            let (sender, receiver) = unbounded();
+            node.senders.insert(neighbour, sender);
+            receivers.insert(neighbour, receiver);
+        }
+        
+        let packet = Packet {
+            pack_type: PacketType::FloodRequest(FloodRequest {
+                flood_id: 7,
+                initiator_id: 2,
+                path_trace: vec![(2, NodeType::Client), (1, NodeType::Drone)],
+            }),
+            routing_header: build_routing_header(vec![2, 1]),
+            session_id: 7,
+        };
+        node.broadcast_packet(packet, 2).expect("Failed to broadcast packet");
+        
+        assert!(receivers[&2].try_recv().is_err());
+        for neighbour in 3..6 {
+            let received = receivers[&neighbour].try_recv().expect("Failed to receive the broadcast");
+            assert_eq!(received.routing_header.hops, vec![1, neighbour]);
+        }
+        assert_eq!(sim_receiver.try_iter().count(), 3);
+        assert_eq!(node.metrics.forwarded, 3);
+    }
+    
+    /// Tests that a fragment received twice is flagged as duplicate once, and that
+    /// fragments of distinct sessions with the same index do not collide.
+    #[test]
+    fn test_is_duplicate() {
+        let mut node = TestNode::new(1);
+        
+        assert!(!node.is_duplicate(42, 0));
+        assert!(node.is_duplicate(42, 0));
+        assert!(!node.is_duplicate(43, 0));
+        assert!(!node.is_duplicate(42, 1));
+    }
+    
+    /// Tests that server types round-trip through their display names.
+    #[test]
+    fn test_server_type_display_from_str() {
+        for server_type in [ServerType::Content, ServerType::Communication, ServerType::Undefined] {
+            let name = server_type.to_string();
+            let parsed: ServerType = name.parse().expect("Failed to parse the server type");
+            assert_eq!(parsed.to_string(), name);
+        }
+        assert_eq!(
+            "Chat".parse::<ServerType>().unwrap_err(),
+            ParseServerTypeError("Chat".to_string())
+        );
+    }
+    
+    /// Tests that liveness probes survive fragmentation.
+    #[test]
+    fn test_ping_pong_fragments() {
+        let packets = SerializableMessage::Ping(3)
+            .into_fragments(1, Encoding::Bincode)
+            .expect("Failed to fragment the message");
+        let fragments: Vec<Fragment> = fragments_of(packets);
+        assert!(matches!(
+            SerializableMessage::from_fragments(&fragments, Encoding::Bincode),
+            Ok(SerializableMessage::Ping(3))
+        ));
+    }
+    
+    /// Tests that a ranged file request is answered with the matching slice,
+    /// and with an error when starting beyond the end of the file.
+    #[test]
+    fn test_file_chunk_response() {
+        let content = b"0123456789";
+        
+        match SerializableMessage::file_chunk_response(5, "f.txt".to_string(), content, 4, 3) {
+            SerializableMessage::FileChunk(5, _, 4, chunk) => assert_eq!(chunk, b"456"),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+        match SerializableMessage::file_chunk_response(5, "f.txt".to_string(), content, 8, 100) {
+            SerializableMessage::FileChunk(5, _, 8, chunk) => assert_eq!(chunk, b"89"),
+            other => panic!("Unexpected response: {:?}", other),
+        }
+        assert!(matches!(
+            SerializableMessage::file_chunk_response(5, "f.txt".to_string(), content, 10, 1),
+            SerializableMessage::ErrorMessage(5, _)
+        ));
+    }
+    
+    /// Tests that file metadata carries the size and the SHA-256 of the content.
+    #[test]
+    fn test_file_metadata_response() {
+        match SerializableMessage::file_metadata_response(5, "abc.txt".to_string(), b"abc") {
+            SerializableMessage::FileMetadataResponse(5, filename, size, hash) => {
+                assert_eq!(filename, "abc.txt");
+                assert_eq!(size, 3);
+                assert_eq!(hash, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+            }
+            other => panic!("Unexpected response: {:?}", other),
+        }
+    }
+    
+    /// Tests that a large upload goes through fragmentation and reassembly unchanged.
+    #[test]
+    fn test_file_upload_round_trip() {
+        let content: Vec<u8> = (0..5_000).map(|i| (i % 256) as u8).collect();
+        let upload = SerializableMessage::FileUpload(1, "upload.bin".to_string(), content.clone());
+        
+        let mut reassembler = FragmentReassembler::new();
+        let mut data = None;
+        for packet in upload.into_fragments(8, Encoding::Bincode).expect("Failed to fragment the upload") {
+            if let PacketType::MsgFragment(fragment) = packet.pack_type {
+                data = reassembler.insert(packet.session_id, fragment);
+            }
+        }
+        let data = data.expect("Failed to reassemble the upload");
+        
+        match SerializableMessage::decode(&data, Encoding::Bincode) {
+            Ok(SerializableMessage::FileUpload(1, filename, received)) => {
+                assert_eq!(filename, "upload.bin");
+                assert_eq!(received, content);
+            }
+            other => panic!("Unexpected message: {:?}", other),
+        }
+    }
+    
+    /// Tests that a message longer than the limit is rejected instead of fragmented.
+    #[test]
+    fn test_message_too_large() {
+        let message = SerializableMessage::FileUpload(1, "big.bin".to_string(), vec![7; 1_000]);
+        
+        assert!(matches!(
+            message.clone().into_fragments_with_limit(1, Encoding::Bincode, 512),
+            Err(CommonError::MessageTooLarge { max: 512, .. })
+        ));
+        assert!(matches!(
+            Checksummed(message.clone()).into_fragments_with_limit(1, Encoding::Bincode, 512),
+            Err(CommonError::MessageTooLarge { max: 512, .. })
+        ));
+        assert!(message.into_fragments_with_limit(1, Encoding::Bincode, 2_048).is_ok());
+    }
+    
+    /// Returns one message of each variant except `Default`, all sent by node 1.
+    fn every_message() -> Vec<SerializableMessage> {
+        vec![
+            SerializableMessage::ServerTypeRequest(1),
+            SerializableMessage::ServerTypeResponse(1, ServerType::Content),
+            SerializableMessage::FileListRequest(1),
+            SerializableMessage::FileListResponse(1, vec!["a.txt".to_string()]),
+            SerializableMessage::FileRequest(1, "a.txt".to_string()),
+            SerializableMessage::FileFound(1, "a.txt".to_string(), "content".to_string()),
+            SerializableMessage::RegisterToCommunicationServer(1),
+            SerializableMessage::RegisterSuccess(1),
+            SerializableMessage::ClientListRequest(1),
+            SerializableMessage::ClientListResponse(1, vec![2, 3]),
+            SerializableMessage::Chat(1, 2, 3, "hello".to_string()),
+            SerializableMessage::ErrorMessage(1, AppError::FileNotFound),
+            SerializableMessage::Ping(1),
+            SerializableMessage::Pong(1),
+            SerializableMessage::FileRequestRange(1, "a.txt".to_string(), 0, 10),
+            SerializableMessage::FileChunk(1, "a.txt".to_string(), 0, vec![1, 2]),
+            SerializableMessage::FileMetadataRequest(1, "a.txt".to_string()),
+            SerializableMessage::FileMetadataResponse(1, "a.txt".to_string(), 2, file_hash(&[1, 2])),
+            SerializableMessage::FileUpload(1, "a.txt".to_string(), vec![1, 2]),
+            SerializableMessage::UploadAck(1, "a.txt".to_string()),
+            SerializableMessage::JoinChannel(1, "general".to_string()),
+            SerializableMessage::LeaveChannel(1, "general".to_string()),
+            SerializableMessage::ChannelMessage(1, 2, "general".to_string(), "hello".to_string()),
+            SerializableMessage::BroadcastChat(1, 2, "hello".to_string()),
+        ]
+    }
+    
+    /// Tests that every variant reports its first argument as sender.
+    #[test]
+    fn test_message_sender() {
+        let messages = every_message();
+        for message in &messages {
+            assert_eq!(message.sender(), Some(1), "wrong sender for {}", message);
+        }
+        assert_eq!(SerializableMessage::Default.sender(), None);
+        
+        assert_eq!(
+            SerializableMessage::FileUpload(1, "a.txt".to_string(), vec![0; 300]).to_string(),
+            "FileUpload(a.txt, 300 bytes)"
+        );
+    }
+    
+    /// Tests that every variant survives a round trip through fragments with each encoding.
+    #[test]
+    fn test_encodings_round_trip() {
+        for encoding in [Encoding::Bincode, Encoding::Json] {
+            let mut messages = every_message();
+            messages.push(SerializableMessage::Default);
+            for message in messages {
+                let expected = format!("{:?}", message);
+                let packets = message
+                    .into_fragments(5, encoding)
+                    .expect("Failed to fragment the message");
+                let fragments: Vec<Fragment> = fragments_of(packets);
+                let received = SerializableMessage::from_fragments(&fragments, encoding)
+                    .expect("Failed to rebuild the message");
+                assert_eq!(format!("{:?}", received), expected, "with {:?}", encoding);
+            }
+        }
+        
+        // Decoding with the wrong encoding is reported, not silently accepted
+        let bytes = SerializableMessage::Ping(1).encode(Encoding::Json);
+        assert!(SerializableMessage::decode(&bytes, Encoding::Bincode).is_err());
+    }
+    
+    /// Tests that compressing a compressible file reduces the number of fragments.
+    #[test]
+    fn test_compressed_file_fragments() {
+        let file = "the same line of a text file\n".repeat(200);
+        let message = SerializableMessage::FileFound(1, "lines.txt".to_string(), file.clone());
+        
+        let plain = message
+            .clone()
+            .into_compressed_fragments(2, Encoding::Bincode, Compression::None)
+            .expect("Failed to fragment the message");
+        let compressed = message
+            .into_compressed_fragments(2, Encoding::Bincode, Compression::Zstd)
+            .expect("Failed to fragment the message");
+        assert!(compressed.len() < plain.len());
+        
+        for packets in [plain, compressed] {
+            let fragments: Vec<Fragment> = fragments_of(packets);
+            match SerializableMessage::from_compressed_fragments(&fragments, Encoding::Bincode) {
+                Ok(SerializableMessage::FileFound(1, _, received)) => assert_eq!(received, file),
+                other => panic!("Unexpected result: {:?}", other),
+            }
+        }
+    }
+    
+    /// Tests that a message decompressing to more than `MAX_MESSAGE_BYTES` is rejected.
+    #[test]
+    fn test_decompressed_message_too_large() {
+        let mut data = vec![Compression::Zstd.header()];
+        data.extend(
+            zstd::bulk::compress(&vec![0u8; MAX_MESSAGE_BYTES + 1], ZSTD_LEVEL)
+                .expect("Failed to compress the data"),
+        );
+        let fragments: Vec<Fragment> = fragments_of(fragment_message(&data, 2));
+        
+        assert!(matches!(
+            SerializableMessage::from_compressed_fragments(&fragments, Encoding::Bincode),
+            Err(CommonError::DeserializeFailed(DeserializeError::DecompressedTooLarge { .. }))
+        ));
+    }
+    
+    /// Tests that the outcome of a tracked command is reported with its id.
+    #[test]
+    fn test_tracked_command_result() {
+        let mut node = TestNode::new(1);
+        let (neighbour_send, _neighbour_recv) = unbounded();
+        node.senders.insert(2, neighbour_send);
+        let (result_send, result_recv) = unbounded();
+        
+        for command_id in [10, 11] {
+            node.dispatch_command(Command::Tracked(
+                command_id,
+                Box::new(Command::Client(ClientCommand::RemoveSender(2))),
+                result_send.clone(),
+            ));
+        }
+        
+        let first = result_recv.try_recv().expect("Expected a command result");
+        assert_eq!(first.command_id, 10);
+        assert!(first.outcome.is_ok());
+        let second = result_recv.try_recv().expect("Expected a command result");
+        assert_eq!(second.command_id, 11);
+        assert!(matches!(second.outcome, Err(CommonError::NextHopUnreachable(2))));
+    }
+    
+    /// Tests that a DumpState command is answered with the node's current state.
+    #[test]
+    fn test_dump_state() {
+        let mut node = TestNode::new(1);
+        node.senders.insert(5, unbounded().0);
+        node.senders.insert(3, unbounded().0);
+        node.seen_flood_ids.insert((7, 1));
+        node.metrics.forwarded = 4;
+        let (snapshot_send, snapshot_recv) = unbounded();
+        
+        node.dispatch_command(Command::DumpState(snapshot_send));
+        
+        let snapshot = snapshot_recv.try_recv().expect("Expected a state snapshot");
+        assert_eq!(
+            snapshot,
+            NodeStateSnapshot {
+                id: 1,
+                neighbors: vec![3, 5],
+                seen_flood_count: 1,
+                metrics: NodeMetrics {
+                    forwarded: 4,
+                    ..NodeMetrics::default()
+                },
+            }
+        );
+    }
+    
+    /// Tests the values returned when adding and removing channels.
+    #[test]
+    fn test_add_remove_channel_results() {
+        let mut node = TestNode::new(1);
+        let (first_send, first_recv) = unbounded();
+        let (second_send, _second_recv) = unbounded();
+        
+        assert!(node.add_channel(2, first_send).is_none());
+        let replaced = node.add_channel(2, second_send).expect("Expected the replaced sender");
+        replaced.send(fragment_message(&[1], 0).remove(0)).expect("Failed to send");
+        assert!(first_recv.try_recv().is_ok());
+        
+        assert!(node.remove_channel(2));
+        assert!(!node.remove_channel(2));
+    }
+    
+    /// Tests that the strict variant of add_channel keeps the existing channel.
+    #[test]
+    fn test_try_add_channel() {
+        let mut node = TestNode::new(1);
+        let (first_send, first_recv) = unbounded();
+        let (second_send, second_recv) = unbounded();
+        
+        assert!(node.try_add_channel(2, first_send).is_ok());
+        assert!(matches!(
+            node.try_add_channel(2, second_send),
+            Err(CommonError::AlreadyNeighbor(2))
+        ));
+        
+        node.senders[&2]
+            .send(fragment_message(&[1], 0).remove(0))
+            .expect("Failed to send");
+        assert!(first_recv.try_recv().is_ok());
+        assert!(second_recv.try_recv().is_err());
+    }
+    
+    /// Tests that crashing answers the queued fragments with ErrorInRouting Nacks.
+    #[test]
+    fn test_crash_nacks_pending_fragments() {
+        let mut node = TestNode::new(2);
+        let (previous_send, previous_recv) = unbounded();
+        node.senders.insert(1, previous_send);
+        let (queue_send, queue_recv) = unbounded();
+        node.receiver = queue_recv;
+        for session_id in [10, 11] {
+            let mut packet = fragment_message(&[1, 2, 3], session_id).remove(0);
+            packet.routing_header = SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![1, 2, 3],
+            };
+            queue_send.send(packet).expect("Failed to queue the packet");
+        }
+        
+        node.crash();
+        
+        assert!(node.receiver.is_empty());
+        for session_id in [10, 11] {
+            let nack = previous_recv.try_recv().expect("Expected a Nack");
+            assert_eq!(nack.session_id, session_id);
+            assert_eq!(nack.routing_header.hops, vec![2, 1]);
+            match nack.pack_type {
+                PacketType::Nack(nack) => assert!(matches!(nack.nack_type, NackType::ErrorInRouting(2))),
+                _ => panic!("Expected a Nack"),
+            }
+        }
+    }
+    
+    /// Tests that a misrouted packet is answered with an UnexpectedRecipient Nack
+    /// going back through the hops that preceded the expected one.
+    #[test]
+    fn test_nack_unexpected_recipient() {
+        let mut node = TestNode::new(7);
+        let mut packet = fragment_message(&[1, 2, 3], 42).remove(0);
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![1, 2, 3, 4],
+        };
+        assert!(node.check_recipient(&packet).is_err());
+        
+        let nack = node.nack_unexpected_recipient(packet);
+        
+        assert_eq!(nack.session_id, 42);
+        assert_eq!(nack.routing_header.hops, vec![7, 2, 1]);
+        assert_eq!(nack.routing_header.hop_index, 0);
+        match nack.pack_type {
+            PacketType::Nack(nack) => {
+                assert_eq!(nack.fragment_index, 0);
+                assert!(matches!(nack.nack_type, NackType::UnexpectedRecipient(7)));
+            }
+            _ => panic!("Expected a Nack"),
+        }
+    }
+    
+    /// Tests that consecutive session ids differ.
+    #[test]
+    fn test_next_session_id() {
+        let mut node = TestNode::new(1);
+        node.rng = StdRng::seed_from_u64(56);
+        
+        let first = node.next_session_id();
+        let second = node.next_session_id();
+        assert_ne!(first, second);
+    }
+    
+    /// Tests that a multicast packet only reaches the listed neighbours.
+    #[test]
+    fn test_multicast_packet() {
+        let mut node = TestNode::new(1);
+        let mut receivers = HashMap::new();
+        for neighbour in [2, 3, 4] {
+            let (send, recv) = unbounded();
+            node.senders.insert(neighbour, send);
+            receivers.insert(neighbour, recv);
+        }
+        
+        node.multicast_packet(fragment_message(&[1, 2, 3], 9).remove(0), &[2, 4, 5])
+            .expect("Failed to multicast packet");
+        
+        for neighbour in [2, 4] {
+            let packet = receivers[&neighbour].try_recv().expect("Expected the packet");
+            assert_eq!(packet.routing_header.hops, vec![1, neighbour]);
+            assert_eq!(packet.routing_header.hop_index, 1);
+        }
+        assert!(receivers[&3].try_recv().is_err());
+        assert_eq!(node.metrics.forwarded, 2);
+    }
+    
+    /// Tests that the parallel broadcast reaches every neighbour but the sender
+    /// and reports closed channels.
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_broadcast_packet_parallel() {
+        let mut node = TestNode::new(1);
+        let mut receivers = HashMap::new();
+        for neighbour in 2..52 {
+            let (send, recv) = unbounded();
+            node.senders.insert(neighbour, send);
+            receivers.insert(neighbour, recv);
+        }
+        receivers.remove(&51);
+        
+        let errors = node
+            .broadcast_packet_parallel(fragment_message(&[1, 2, 3], 9).remove(0), 2)
+            .expect("Failed to broadcast packet");
+        
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], (51, CommonError::ChannelClosed(51))));
+        assert!(receivers[&2].try_recv().is_err());
+        for neighbour in 3..51 {
+            let packet = receivers[&neighbour].try_recv().expect("Expected the packet");
+            assert_eq!(packet.routing_header.hops, vec![1, neighbour]);
+            assert_eq!(packet.routing_header.hop_index, 1);
+        }
+        assert_eq!(node.metrics.forwarded, 48);
+    }
+    
+    /// Tests that the parallel broadcast, like `broadcast_packet`, sends nothing and fails when
+    /// the simulation controller is unreachable, and skips blocked neighbours.
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_broadcast_packet_parallel_checks() {
+        let mut node = TestNode::new(1);
+        let (sender_2, receiver_2) = unbounded();
+        let (sender_3, receiver_3) = unbounded();
+        node.senders.insert(2, sender_2);
+        node.senders.insert(3, sender_3);
+        node.set_broadcast_blocklist(HashSet::from([3]));
+        
+        let packet = fragment_message(&[1, 2, 3], 59).remove(0);
+        let errors = node
+            .broadcast_packet_parallel(packet.clone(), 4)
+            .expect("Failed to broadcast packet");
+        assert!(errors.is_empty());
+        assert!(receiver_2.try_recv().is_ok());
+        assert!(receiver_3.try_recv().is_err());
+        
+        let (sim_sender, sim_receiver) = unbounded();
+        node.sim_controller = sim_sender;
+        drop(sim_receiver);
+        assert!(matches!(
+            node.broadcast_packet_parallel(packet, 4),
+            Err(CommonError::ControllerUnreachable)
+        ));
+        assert!(receiver_2.try_recv().is_err());
+        assert_eq!(node.metrics.forwarded, 1);
+    }
+    
+    /// Tests fragmenting with a smaller fragment size, including a short final fragment.
+    #[test]
+    fn test_custom_fragment_size() {
+        let data: Vec<u8> = (0..100).collect();
+        let fragments: Vec<Fragment> = fragments_of(fragment_message_with_size(&data, 3, 32))
+            .into_iter()
+            .rev()
             .collect();
         
-        // Iterate on the neighbours list
-        for (&node_id, sender) in neighbours.iter() {
-            let mut packet_to_send = packet.clone();
-            packet_to_send.routing_header = SourceRoutingHeader {
-                hop_index: 1,
-                hops: vec![self.get_id(), node_id],
-            };
-            // Send a clone of the packet and a simulation event
-            if let Err(e) = self
-                .get_sim_contr_send()
-                .send(DroneEvent::PacketSent(packet_to_send.clone()))
-            {
-                log_error!(self.get_id(), "Failed to send PacketSent event: {:?}", e);
-            }
-            if let Err(e) = sender.send(packet_to_send) {
-                println!("Failed to send packet to NodeId {:?}: {:?}", node_id, e);
-            }
+        assert_eq!(fragments.len(), 4);
+        assert_eq!(fragments[0].length, 4);
+        assert!(fragments[1..].iter().all(|fragment| fragment.length == 32));
+        assert_eq!(
+            reassemble_fragments_with_size(&fragments, 32).expect("Failed to reassemble"),
+            data
+        );
+        assert!(matches!(
+            reassemble_fragments_with_size(&fragments, FRAGMENT_SIZE),
+            Err(DeserializeError::InvalidLength(_))
+        ));
+        
+        let mut reassembler = FragmentReassembler::new();
+        let mut received = None;
+        for fragment in fragments {
+            received = reassembler.insert(3, fragment);
         }
+        assert_eq!(received, Some(data));
     }
     
-    /// Reverses the routing direction of the provided packet.
-    ///
-    /// This is achieved by removing any nodes beyond the current hop in the routing header,
-    /// reversing the order of the hops, and updating the header so the packet can be sent back.
-    ///
-    /// # Arguments
-    ///
-    /// * `packet` - The packet whose routing header is to be reversed.
-    fn reverse_packet_routing_direction(&self, packet: &mut Packet) {
-        // a. Create the route back using the current hops
-        let mut hops_vec: Vec<NodeId> = packet.routing_header.hops.clone();
+    /// Tests that forwarding a packet whose hop index is past the end of its route fails cleanly.
+    #[test]
+    fn test_forward_packet_route_exhausted() {
+        let mut node = TestNode::new(2);
+        node.senders.insert(3, unbounded().0);
+        let mut packet = fragment_message(&[1, 2, 3], 5).remove(0);
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 3,
+            hops: vec![1, 2, 3],
+        };
+        assert!(matches!(node.forward_packet(packet.clone()), Err(CommonError::RouteExhausted)));
         
-        // Remove nodes that should no longer receive the packet
-        hops_vec.drain(packet.routing_header.hop_index + 1..=hops_vec.len() - 1);
+        // The last hop has nobody to forward the packet to either
+        packet.routing_header.hop_index = 2;
+        assert!(matches!(node.forward_packet(packet), Err(CommonError::RouteExhausted)));
+        assert_eq!(node.metrics.forwarded, 0);
+    }
+    
+    /// Tests that the forwarded packet points at the next hop.
+    #[test]
+    fn test_forward_packet_increments_hop_index() {
+        let mut node = TestNode::new(2);
+        let (next_send, next_recv) = unbounded();
+        node.senders.insert(3, next_send);
+        let mut packet = fragment_message(&[1, 2, 3], 5).remove(0);
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2, 3, 4],
+        };
         
-        // Reverse the order to set up the return path
-        hops_vec.reverse();
+        node.forward_packet(packet).expect("Failed to forward packet");
         
-        let route_back: SourceRoutingHeader = SourceRoutingHeader {
-            hop_index: 1, // Start from the first hop
-            hops: hops_vec,
-        };
+        let forwarded = next_recv.try_recv().expect("Expected the packet");
+        assert_eq!(forwarded.routing_header.hop_index, 2);
+        assert_eq!(forwarded.routing_header.hops[forwarded.routing_header.hop_index], 3);
+    }
+    
+    /// Tests that a Heartbeat command makes the node emit a heartbeat with its id,
+    /// and that periodic heartbeats respect the interval.
+    #[test]
+    fn test_heartbeat_command() {
+        let mut node = TestNode::new(4);
+        let (heartbeat_send, heartbeat_recv) = unbounded();
+        
+        // Without an emitter, the command is ignored
+        node.dispatch_command(Command::Heartbeat);
         
-        // b. Update the packet's routing header
-        packet.routing_header = route_back;
+        node.heartbeat = Some(HeartbeatEmitter::new(heartbeat_send, Duration::from_secs(3600)));
+        node.dispatch_command(Command::Heartbeat);
+        let heartbeat = heartbeat_recv.try_recv().expect("Expected a heartbeat");
+        assert_eq!(heartbeat.node_id, 4);
+        
+        // The command's heartbeat is recent, so the periodic one is not due yet
+        node.heartbeat_if_due();
+        assert!(heartbeat_recv.try_recv().is_err());
     }
     
-    /// Adds a communication channel for a neighbouring node.
-    ///
-    /// # Arguments
-    ///
-    /// * `id` - The node ID of the neighbour.
-    /// * `sender` - The sender channel associated with the neighbour.
-    fn add_channel(&mut self, id: NodeId, sender: Sender<Packet>) {
-        let packet_send = self.get_packet_send();
-        packet_send.insert(id, sender);
+    /// Tests that a dead simulation controller stops forwarding and broadcasting,
+    /// and is reported to the caller.
+    #[test]
+    fn test_controller_unreachable() {
+        let mut node = TestNode::new(1);
+        let (sim_sender, sim_receiver) = unbounded();
+        node.sim_controller = sim_sender;
+        drop(sim_receiver);
+        let (sender, receiver) = unbounded();
+        node.senders.insert(2, sender);
+        node.senders.insert(3, unbounded().0);
+        
+        assert!(matches!(
+            node.emit_event(DroneEvent::PacketSent(fragment_message(&[1], 4).remove(0))),
+            Err(CommonError::ControllerUnreachable)
+        ));
+        let mut packet = fragment_message(&[1, 2, 3], 5).remove(0);
+        packet.routing_header = build_routing_header(vec![1, 2]);
+        assert!(matches!(node.forward_packet(packet.clone()), Err(CommonError::ControllerUnreachable)));
+        assert!(matches!(node.broadcast_packet(packet, 4), Err(CommonError::ControllerUnreachable)));
+        
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(node.metrics.forwarded, 0);
     }
     
-    /// Removes the communication channel associated with a neighbouring node.
-    ///
-    /// Logs an error if no such channel exists.
-    ///
-    /// # Arguments
-    ///
-    /// * `id` - The node ID of the neighbour to remove.
-    fn remove_channel(&mut self, id: NodeId) {
-        if !self.get_packet_send().contains_key(&id) {
-            log_error!(
-                self.get_id(),
-                "Error! The current node {} has no neighbour node {}.",
-                self.get_id(),
-                id
-            );
-            return;
+    /// Tests that the next hops are picked with frequencies close to their weights,
+    /// and that zero weights are never picked.
+    #[test]
+    fn test_weighted_next_hop() {
+        let mut node = TestNode::new(1);
+        node.rng = StdRng::seed_from_u64(72);
+        let candidates = [(2, 1), (3, 3), (4, 0), (5, 6)];
+        
+        let draws = 100_000;
+        let mut counts: HashMap<NodeId, u32> = HashMap::new();
+        for _ in 0..draws {
+            let next_hop = node.weighted_next_hop(&candidates).expect("Expected a next hop");
+            *counts.entry(next_hop).or_default() += 1;
         }
-        self.get_packet_send().remove(&id);
+        
+        assert!(!counts.contains_key(&4));
+        for (node_id, weight) in [(2, 1), (3, 3), (5, 6)] {
+            let frequency = f64::from(counts[&node_id]) / f64::from(draws);
+            let expected = f64::from(weight) / 10.0;
+            assert!((frequency - expected).abs() < 0.01, "node {}: {} vs {}", node_id, frequency, expected);
+        }
+        assert_eq!(node.weighted_next_hop(&[]), None);
+        assert_eq!(node.weighted_next_hop(&[(2, 0)]), None);
     }
-}
-
-// ------------------------------------------------------------------------------------------------------
-// ----------------------------------- TESTS ------------------------------------------------------------
-// ------------------------------------------------------------------------------------------------------
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crossbeam_channel::unbounded;
-    use rand::SeedableRng;
     
-    struct TestNode {
-        id: NodeId,
-        seen_flood_ids: HashSet<String>,
-        senders: HashMap<NodeId, Sender<Packet>>,
-        receiver: Receiver<Packet>,
-        rng: StdRng,
-        sim_controller: Sender<DroneEvent>,
+    /// Tests that `run_once` handles a received packet, then a command, then times out.
+    #[test]
+    fn test_run_once() {
+        let mut node = TestNode::new(1);
+        let (packet_send, packet_recv) = unbounded();
+        let (command_send, command_recv) = unbounded();
+        let (next_send, next_recv) = unbounded();
+        node.receiver = packet_recv;
+        node.commands = Some(command_recv);
+        node.senders.insert(3, next_send);
+        
+        packet_send
+            .send(Packet {
+                pack_type: PacketType::FloodRequest(FloodRequest {
+                    flood_id: 73,
+                    initiator_id: 2,
+                    path_trace: vec![(2, NodeType::Client)],
+                }),
+                routing_header: build_routing_header(vec![2, 1]),
+                session_id: 73,
+            })
+            .expect("Failed to send packet");
+        assert_eq!(node.run_once(Duration::from_millis(100)), RunOutcome::Handled);
+        assert_eq!(next_recv.try_recv().expect("Expected the flood request").session_id, 73);
+        
+        let (snapshot_send, snapshot_recv) = unbounded();
+        command_send
+            .send(Command::DumpState(snapshot_send))
+            .expect("Failed to send command");
+        assert_eq!(node.run_once(Duration::from_millis(100)), RunOutcome::Handled);
+        assert_eq!(snapshot_recv.try_recv().expect("Expected the snapshot").id, 1);
+        
+        assert_eq!(node.run_once(Duration::from_millis(10)), RunOutcome::TimedOut);
+        drop(packet_send);
+        assert_eq!(node.run_once(Duration::from_millis(10)), RunOutcome::Disconnected);
     }
     
-    impl NetworkNode for TestNode {
-        /// Returns the unique identifier for this test node.
-        fn get_id(&self) -> NodeId {
-            self.id
-        }
+    /// Tests that `run` handles the packets it receives and stops on `Command::Shutdown`.
+    #[test]
+    fn test_run_until_shutdown() {
+        let mut node = TestNode::new(1);
+        let (packet_send, packet_recv) = unbounded();
+        let (command_send, command_recv) = unbounded();
+        let (next_send, next_recv) = unbounded();
+        node.receiver = packet_recv;
+        node.commands = Some(command_recv);
+        node.senders.insert(3, next_send);
         
-        /// Provides mutable access to the set of flood request IDs seen by this test node.
-        fn get_seen_flood_ids(&mut self) -> &mut HashSet<String> {
-            &mut self.seen_flood_ids
-        }
+        let handle = std::thread::spawn(move || {
+            node.run();
+            node
+        });
+        packet_send
+            .send(Packet {
+                pack_type: PacketType::FloodRequest(FloodRequest {
+                    flood_id: 74,
+                    initiator_id: 2,
+                    path_trace: vec![(2, NodeType::Client)],
+                }),
+                routing_header: build_routing_header(vec![2, 1]),
+                session_id: 74,
+            })
+            .expect("Failed to send packet");
+        let forwarded = next_recv
+            .recv_timeout(Duration::from_secs(1))
+            .expect("Expected the flood request");
+        assert_eq!(forwarded.session_id, 74);
         
-        /// Returns a mutable reference to the mapping of neighbour nodes to their sender channels.
-        fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>> {
-            &mut self.senders
-        }
+        command_send.send(Command::Shutdown).expect("Failed to send command");
+        let node = handle.join().expect("Failed to join the node thread");
+        assert_eq!(node.metrics.forwarded, 1);
+        assert!(node.is_shutting_down());
+    }
+    
+    /// Tests that a `Command::Shutdown` sets the shutdown flag and stops `run_once`.
+    #[test]
+    fn test_shutdown_command() {
+        let mut node = TestNode::new(1);
+        let (command_send, command_recv) = unbounded();
+        node.commands = Some(command_recv);
+        let (_packet_send, packet_recv) = unbounded();
+        node.receiver = packet_recv;
         
-        /// Retrieves a reference to the receiver channel for incoming packets.
-        fn get_packet_receiver(&self) -> &Receiver<Packet> {
-            &self.receiver
-        }
+        assert!(!node.is_shutting_down());
+        command_send.send(Command::Shutdown).expect("Failed to send command");
+        assert_eq!(node.run_once(Duration::from_millis(100)), RunOutcome::Shutdown);
+        assert!(node.is_shutting_down());
+    }
+    
+    /// Tests that consecutive flood ids increase and do not repeat within a node.
+    #[test]
+    fn test_next_flood_id() {
+        let mut node = TestNode::new(1);
         
-        /// Returns a mutable reference to the test node's random number generator.
-        fn get_random_generator(&mut self) -> &mut StdRng {
-            &mut self.rng
+        let ids: Vec<u64> = (0..100).map(|_| node.next_flood_id()).collect();
+        assert_eq!(ids[0], 1);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(TestNode::new(2).next_flood_id(), 1);
+    }
+    
+    /// Tests that an initiated flood reaches every neighbour with a path trace
+    /// starting at the initiator, and is recorded as seen.
+    #[test]
+    fn test_initiate_flood() {
+        let mut node = TestNode::new(1);
+        let (sim_sender, sim_receiver) = unbounded();
+        node.sim_controller = sim_sender;
+        let mut receivers = HashMap::new();
+        for neighbour in [2, 3, 4] {
+            let (send, recv) = unbounded();
+            node.senders.insert(neighbour, send);
+            receivers.insert(neighbour, recv);
         }
         
-        /// Returns a reference to the simulation controller sender channel.
-        fn get_sim_contr_send(&self) -> &Sender<DroneEvent> {
-            &self.sim_controller
-        }
+        let flood_id = node.initiate_flood().expect("Failed to initiate the flood");
         
-        /// Test implementation for handling a routed packet.
-        /// This function is unimplemented in the test node.
-        fn handle_routed_packet(&mut self, _packet: Packet) -> bool {
-            unimplemented!()
+        for (neighbour, receiver) in &receivers {
+            let packet = receiver.try_recv().expect("Expected the flood request");
+            assert_eq!(packet.routing_header.hops, vec![1, *neighbour]);
+            assert_eq!(packet.session_id, flood_id);
+            match packet.pack_type {
+                PacketType::FloodRequest(request) => {
+                    assert_eq!(request.flood_id, flood_id);
+                    assert_eq!(request.initiator_id, 1);
+                    assert_eq!(request.path_trace[0].0, 1);
+                    assert_eq!(request.path_trace.len(), 1);
+                }
+                _ => panic!("Expected a flood request"),
+            }
         }
+        assert_eq!(sim_receiver.try_iter().count(), 3);
+        assert!(node.seen_flood_ids.contains(&(1, flood_id)));
+    }
+    
+    /// Tests that a flood request with an empty path trace is dropped without panicking.
+    #[test]
+    fn test_flood_request_with_empty_path_trace() {
+        let mut node = TestNode::new(1);
+        let (sim_sender, sim_receiver) = unbounded();
+        node.sim_controller = sim_sender;
+        let (sender, receiver) = unbounded();
+        node.senders.insert(2, sender);
+        node.senders.insert(3, unbounded().0);
         
-        /// Test implementation for handling a command.
-        /// This function is unimplemented in the test node.
-        fn handle_command(&mut self, _command: Command) {
-            unimplemented!()
+        node.handle_flood_request(Packet {
+            pack_type: PacketType::FloodRequest(FloodRequest {
+                flood_id: 78,
+                initiator_id: 2,
+                path_trace: Vec::new(),
+            }),
+            routing_header: build_routing_header(vec![2, 1]),
+            session_id: 78,
+        });
+        
+        assert!(receiver.try_recv().is_err());
+        assert!(matches!(sim_receiver.try_recv(), Ok(DroneEvent::PacketDropped(_))));
+        assert_eq!(node.metrics.dropped, 1);
+    }
+    
+    /// Tests that reversing a header keeps the hops up to the current one, in reverse order,
+    /// including when the hop index is out of bounds or the route empty.
+    #[test]
+    fn test_reverse_routing_header() {
+        let header = SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![1, 2, 3, 4],
+        };
+        let reversed = reverse_routing_header(&header);
+        assert_eq!(reversed.hops, vec![3, 2, 1]);
+        assert_eq!(reversed.hop_index, 0);
+        
+        let past_the_end = SourceRoutingHeader {
+            hop_index: usize::MAX,
+            hops: vec![1, 2],
+        };
+        assert_eq!(reverse_routing_header(&past_the_end).hops, vec![2, 1]);
+        
+        let empty = SourceRoutingHeader {
+            hop_index: 0,
+            hops: Vec::new(),
+        };
+        assert!(reverse_routing_header(&empty).hops.is_empty());
+    }
+    
+    /// Tests that the transportable commands survive a JSON round trip,
+    /// and that commands carrying a channel are rejected.
+    #[test]
+    fn test_serializable_command() {
+        let commands = vec![
+            Command::Client(ClientCommand::Chat(5, 6, "hello".to_string())),
+            Command::Server(ServerCommand::SetServerType(ServerType::Content)),
+            Command::Drone(DroneCommand::SetPacketDropRate(0.5)),
+            Command::Shutdown,
+        ];
+        for command in commands {
+            let serializable = SerializableCommand::try_from(command).unwrap_or_else(|_| panic!("Expected a serializable command"));
+            let json = serde_json::to_string(&serializable).expect("Failed to serialize command");
+            let decoded: SerializableCommand = serde_json::from_str(&json).expect("Failed to deserialize command");
+            assert_eq!(serde_json::to_string(&decoded).expect("Failed to serialize command"), json);
         }
+        
+        let decoded: SerializableCommand =
+            serde_json::from_str(r#"{"Drone":{"RemoveSender":3}}"#).expect("Failed to deserialize command");
+        assert!(matches!(Command::from(decoded), Command::Drone(DroneCommand::RemoveSender(3))));
+        
+        let (sender, _receiver) = unbounded();
+        assert!(SerializableCommand::try_from(Command::DumpState(sender)).is_err());
+        let (sender, _receiver) = unbounded();
+        let add_sender = SerializableCommand::Client(ClientCommand::AddSender(2, sender));
+        assert!(serde_json::to_string(&add_sender).is_err());
     }
     
-    impl TestNode {
-        /// Creates a new test node with the specified identifier.
-        fn new(id: NodeId) -> Self {
-            Self {
-                id,
-                seen_flood_ids: HashSet::new(),
-                senders: HashMap::new(),
-                receiver: unbounded().1,
-                rng: StdRng::from_entropy(),
-                sim_controller: unbounded().0,
-            }
+    /// Tests that replaying the recording of a node on an identical node
+    /// reproduces the packets it forwarded.
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join("dr_ones_recording_81.jsonl");
+        let flood_requests: Vec<Packet> = (0..3)
+            .map(|flood_id| Packet {
+                pack_type: PacketType::FloodRequest(FloodRequest {
+                    flood_id,
+                    initiator_id: 2,
+                    path_trace: vec![(2, NodeType::Client)],
+                }),
+                routing_header: build_routing_header(vec![2, 1]),
+                session_id: flood_id,
+            })
+            .collect();
+        let with_neighbours = || {
+            let mut node = TestNode::new(1);
+            let (sender, receiver) = unbounded();
+            node.senders.insert(2, unbounded().0);
+            node.senders.insert(3, sender);
+            (node, receiver)
+        };
+        
+        let (mut recorded_node, recorded_output) = with_neighbours();
+        recorded_node.recorder = Some(PacketRecorder::create(&path).expect("Failed to create the recording"));
+        for packet in flood_requests.iter().chain(&flood_requests) {
+            recorded_node.handle_packet(packet.clone());
         }
+        
+        let (mut replaying_node, replayed_output) = with_neighbours();
+        assert_eq!(crate::replay(&mut replaying_node, &path).expect("Failed to replay"), 6);
+        
+        let recorded: Vec<String> = recorded_output.try_iter().map(|p| format!("{:?}", p)).collect();
+        let replayed: Vec<String> = replayed_output.try_iter().map(|p| format!("{:?}", p)).collect();
+        // The duplicates are answered towards node 2: only the first requests reach node 3
+        assert_eq!(recorded.len(), 3);
+        assert_eq!(recorded, replayed);
+        std::fs::remove_file(&path).expect("Failed to remove the recording");
     }
     
-    /// Tests the `forward_packet` function to ensure that a packet is correctly forwarded to the next hop
-    /// and that a simulation event is generated.
+    /// Tests that a fragment dropped because of the packet drop rate
+    /// is recorded with `DropReason::RandomDrop`.
     #[test]
-    fn test_forward_packet() {
-        // Create a test node with ID 1
+    fn test_drop_record_reason() {
         let mut node = TestNode::new(1);
+        node.pdr = 1.0;
+        let (record_send, record_recv) = unbounded();
+        node.drop_records = Some(record_send);
         
-        // Set up communication channel to node 2
+        let mut packet = fragment_message(&[1, 2, 3], 82).remove(0);
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![2, 1, 3],
+        };
+        assert!(node.maybe_drop(&packet));
+        
+        let record = record_recv.try_recv().expect("Expected a drop record");
+        assert_eq!(record.node_id, 1);
+        assert_eq!(record.reason, DropReason::RandomDrop);
+        assert_eq!(record.packet.session_id, 82);
+        assert!(record_recv.try_recv().is_err());
+    }
+    
+    /// Tests that a valid route passes validation.
+    #[test]
+    fn test_validate_route_valid() {
+        let header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2, 3, 4],
+        };
+        assert!(validate_route(&header).is_ok());
+    }
+    
+    /// Tests that a route repeating a node, or an empty one, is rejected and not forwarded.
+    #[test]
+    fn test_validate_route_duplicate_hop() {
+        let header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2, 3, 2, 4],
+        };
+        assert!(matches!(validate_route(&header), Err(CommonError::InvalidRoute(2))));
+        assert!(matches!(
+            validate_route(&SourceRoutingHeader { hop_index: 0, hops: Vec::new() }),
+            Err(CommonError::EmptyRoute)
+        ));
+        
+        let mut node = TestNode::new(2);
         let (sender, receiver) = unbounded();
-        let (sim_sender, sim_receiver) = unbounded();
-        node.senders.insert(2, sender);
-        node.sim_controller = sim_sender;
+        node.senders.insert(3, sender);
+        let mut packet = fragment_message(&[1, 2, 3], 83).remove(0);
+        packet.routing_header = header;
+        assert!(matches!(node.forward_packet(packet), Err(CommonError::InvalidRoute(2))));
+        assert!(receiver.try_recv().is_err());
+    }
+    
+    /// Tests the remaining hops and the next hop at the start, middle and end of a route,
+    /// and past its end.
+    #[test]
+    fn test_remaining_hops() {
+        let mut header = SourceRoutingHeader {
+            hop_index: 0,
+            hops: vec![1, 2, 3, 4],
+        };
+        assert_eq!(remaining_hops(&header), &[2, 3, 4]);
+        assert_eq!(next_hop(&header), Some(2));
         
-        // Create a test packet with routing header [1, 2] where the current hop is at index 0.
-        let packet = Packet {
-            pack_type: wg_2024::packet::PacketType::Ack(wg_2024::packet::Ack { fragment_index: 0 }),
-            routing_header: wg_2024::network::SourceRoutingHeader {
+        header.hop_index = 2;
+        assert_eq!(remaining_hops(&header), &[4]);
+        assert_eq!(next_hop(&header), Some(4));
+        
+        header.hop_index = 3;
+        assert!(remaining_hops(&header).is_empty());
+        assert_eq!(next_hop(&header), None);
+        
+        header.hop_index = usize::MAX;
+        assert!(remaining_hops(&header).is_empty());
+        assert_eq!(next_hop(&header), None);
+    }
+    
+    /// Tests that the flood responses received by the initiator are tracked, and that the flood
+    /// is complete once the timeout has elapsed since the last new responder.
+    #[test]
+    fn test_flood_tracker() {
+        let timeout = Duration::from_secs(1);
+        let mut node = TestNode::new(1);
+        node.senders.insert(2, unbounded().0);
+        node.flood_tracker = Some(FloodTracker::new(0, timeout));
+        let flood_id = node.initiate_flood().expect("Failed to initiate flood");
+        
+        let response = |path_trace: Vec<(NodeId, NodeType)>, flood_id| Packet {
+            routing_header: build_routing_header(path_trace.iter().rev().map(|(id, _)| *id).collect()),
+            session_id: flood_id,
+            pack_type: PacketType::FloodResponse(FloodResponse { flood_id, path_trace }),
+        };
+        node.handle_packet(response(vec![(1, NodeType::Client), (2, NodeType::Drone)], flood_id));
+        node.handle_packet(response(
+            vec![(1, NodeType::Client), (2, NodeType::Drone), (3, NodeType::Server)],
+            flood_id,
+        ));
+        node.handle_packet(response(vec![(1, NodeType::Client), (2, NodeType::Drone)], flood_id));
+        node.handle_packet(response(vec![(1, NodeType::Client), (4, NodeType::Drone)], flood_id + 1));
+        
+        let tracker = node.flood_tracker.as_ref().expect("Flood tracker not set");
+        assert_eq!(tracker.flood_id(), flood_id);
+        assert_eq!(tracker.responders(), &HashSet::from([2, 3]));
+        assert!(!tracker.is_complete_at(Instant::now()));
+        assert!(tracker.is_complete_at(Instant::now() + timeout));
+    }
+    
+    /// Tests that a blocklisted neighbour receives no broadcast while the others do, and that
+    /// it is unreachable as a next hop until the blocklist is cleared.
+    #[test]
+    fn test_broadcast_blocklist() {
+        let mut node = TestNode::new(1);
+        let (sender_2, receiver_2) = unbounded();
+        let (sender_3, receiver_3) = unbounded();
+        node.senders.insert(2, sender_2);
+        node.senders.insert(3, sender_3);
+        node.set_broadcast_blocklist(HashSet::from([3]));
+        
+        node.initiate_flood().expect("Failed to initiate flood");
+        assert!(matches!(
+            receiver_2.try_recv().map(|packet| packet.pack_type),
+            Ok(PacketType::FloodRequest(_))
+        ));
+        assert!(receiver_3.try_recv().is_err());
+        
+        let mut packet = fragment_message(&[1, 2, 3], 93).remove(0);
+        packet.routing_header = build_routing_header(vec![1, 3]);
+        assert!(matches!(
+            node.forward_packet(packet.clone()),
+            Err(CommonError::NextHopUnreachable(3))
+        ));
+        assert!(receiver_3.try_recv().is_err());
+        
+        node.set_broadcast_blocklist(HashSet::new());
+        node.forward_packet(packet).expect("Failed to forward packet");
+        assert!(receiver_3.try_recv().is_ok());
+    }
+    
+    /// Tests that the (N+1)th packet of a session relayed by a node is dropped with an
+    /// `ErrorInRouting` Nack, while the packets of other sessions still go through.
+    #[test]
+    fn test_max_forwards() {
+        let mut node = TestNode::new(2);
+        node.max_forwards = 3;
+        node.forward_counts = Some(ForwardCounts::new());
+        let (sender_1, receiver_1) = unbounded();
+        let (sender_3, receiver_3) = unbounded();
+        node.senders.insert(1, sender_1);
+        node.senders.insert(3, sender_3);
+        
+        let relayed = |session_id| {
+            let mut packet = fragment_message(&[1, 2, 3], session_id).remove(0);
+            packet.routing_header = SourceRoutingHeader {
                 hop_index: 1,
-                hops: vec![1, 2],
-            },
-            session_id: 42,
+                hops: vec![1, 2, 3],
+            };
+            packet
         };
+        for _ in 0..3 {
+            node.forward_packet(relayed(94)).expect("Failed to forward packet");
+        }
+        assert_eq!(receiver_3.try_iter().count(), 3);
         
-        // Test forwarding the packet from node 1 to node 2
-        node.forward_packet(packet.clone());
+        assert!(matches!(
+            node.forward_packet(relayed(94)),
+            Err(CommonError::ForwardLimitExceeded(94))
+        ));
+        assert!(receiver_3.try_recv().is_err());
+        let nack = receiver_1.try_recv().expect("Expected a Nack");
+        assert!(matches!(
+            nack.pack_type,
+            PacketType::Nack(Nack { nack_type: NackType::ErrorInRouting(2), .. })
+        ));
         
-        // Verify the packet was received by node 2
-        let received = receiver.try_recv().expect("Failed to receive packet");
-        assert_eq!(received.session_id, 42);
+        node.forward_packet(relayed(95)).expect("Failed to forward packet");
+        assert_eq!(receiver_3.try_iter().count(), 1);
+    }
+    
+    /// Tests that the chat builder puts each id in its place, and refuses a missing field.
+    #[test]
+    fn test_chat_builder() {
+        let chat = ChatBuilder::new().to(3).text("Hello").from(1).via(2).build();
+        assert!(matches!(
+            chat,
+            Ok(SerializableMessage::Chat(1, 2, 3, ref text)) if text == "Hello"
+        ));
         
-        // Verify that a simulation event was generated
-        let sim_event = sim_receiver.try_recv().expect("Failed to receive simulation controller event");
-        match sim_event {
-            DroneEvent::PacketSent(p) => assert_eq!(p.session_id, 42),
-            _ => panic!("Expected PacketSent event"),
+        let missing_server = ChatBuilder::new().from(1).to(3).text("Hello").build();
+        assert!(matches!(missing_server, Err(CommonError::MissingField("via"))));
+        assert!(matches!(ChatBuilder::new().build(), Err(CommonError::MissingField("from"))));
+    }
+    
+    /// Tests that messages survive a round trip unchanged, and that equal messages are
+    /// deduplicated in a set.
+    #[test]
+    fn test_message_equality() {
+        let messages = vec![
+            SerializableMessage::ServerTypeResponse(4, ServerType::Communication),
+            SerializableMessage::ErrorMessage(4, AppError::Other("oops".to_string())),
+            SerializableMessage::FileChunk(4, "file.txt".to_string(), 8, vec![1, 2, 3]),
+        ];
+        for message in &messages {
+            for encoding in [Encoding::Bincode, Encoding::Json] {
+                let decoded = SerializableMessage::decode(&message.encode(encoding), encoding)
+                    .expect("Failed to decode the message");
+                assert_eq!(&decoded, message);
+            }
+        }
+        
+        let seen: HashSet<SerializableMessage> = messages.iter().chain(&messages).cloned().collect();
+        assert_eq!(seen.len(), messages.len());
+        assert_ne!(messages[0], SerializableMessage::ServerTypeResponse(4, ServerType::Content));
+    }
+    
+    /// Tests the degree and the sorted neighbours of a node with three neighbours.
+    #[test]
+    fn test_neighbors() {
+        let mut node = TestNode::new(1);
+        assert_eq!(node.neighbor_count(), 0);
+        assert!(node.neighbors().is_empty());
+        
+        for neighbor in [7, 3, 5] {
+            node.add_channel(neighbor, unbounded().0);
         }
+        assert_eq!(node.neighbor_count(), 3);
+        assert_eq!(node.neighbors(), vec![3, 5, 7]);
+    }
+    
+    /// Tests that the route followed back by a flood response is cached, that a shorter route
+    /// replaces it, that an invalid route is not cached, and that an `ErrorInRouting` Nack drops
+    /// the routes through the failing node.
+    #[test]
+    fn test_route_cache() {
+        let mut node = TestNode::new(1);
+        node.route_cache = Some(RouteCache::new());
+        // The responses are received at their last hop
+        let response = |path_trace: Vec<NodeId>| Packet {
+            routing_header: SourceRoutingHeader {
+                hop_index: path_trace.len() - 1,
+                hops: path_trace.iter().rev().copied().collect(),
+            },
+            session_id: 1,
+            pack_type: PacketType::FloodResponse(FloodResponse {
+                flood_id: 1,
+                path_trace: path_trace.into_iter().map(|id| (id, NodeType::Drone)).collect(),
+            }),
+        };
+        node.handle_packet(response(vec![1, 2, 3, 4, 5]));
+        node.handle_packet(response(vec![1, 6, 5]));
+        node.handle_packet(response(vec![1, 2, 3, 7, 4, 5]));
+        node.handle_packet(response(vec![1, 2, 3]));
+        node.handle_packet(response(vec![1, 8, 9, 8, 10]));
+        
+        let route_cache = node.route_cache.as_ref().expect("Route cache not set");
+        assert_eq!(route_cache.get(5), Some(&[1, 6, 5][..]));
+        assert_eq!(route_cache.get(3), Some(&[1, 2, 3][..]));
+        assert_eq!(route_cache.get(10), None);
+        
+        let nack = Nack {
+            fragment_index: 0,
+            nack_type: NackType::ErrorInRouting(2),
+        };
+        node.handle_nack(&nack, 100);
+        let route_cache = node.route_cache.as_ref().expect("Route cache not set");
+        assert_eq!(route_cache.get(3), None);
+        assert_eq!(route_cache.get(5), Some(&[1, 6, 5][..]));
     }
 }
@@ -0,0 +1,215 @@
+//! State shared by every implementation of `NetworkNode`.
+
+use crossbeam_channel::{Receiver, Sender};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::{HashMap, HashSet};
+use wg_2024::{controller::DroneEvent, network::NodeId, packet::Packet};
+
+use crate::network_node::NodeMetrics;
+use crate::seen_flood_cache::SeenFloodCache;
+
+/// The fields every network node needs to implement the getters of `NetworkNode`.
+///
+/// Drones, clients and servers can embed a `NodeCore` instead of declaring these fields
+/// themselves, and delegate the corresponding trait methods to it:
+///
+/// * `get_id` returns `core.id`;
+/// * `get_seen_flood_ids` returns `&mut core.seen_flood_ids`;
+/// * `get_packet_send` and `packet_send_ref` return `core.packet_send`;
+/// * `get_packet_receiver` returns `&core.packet_recv`;
+/// * `get_random_generator` returns `&mut core.rng`;
+/// * `get_sim_contr_send` returns `&core.sim_contr_send`;
+/// * `get_metrics` returns `&mut core.metrics`;
+/// * `get_last_flood_id` returns `&mut core.last_flood_id`;
+/// * optionally, `broadcast_blocklist_ref` and `get_broadcast_blocklist` return
+///   `core.broadcast_blocklist`, to support `set_broadcast_blocklist`.
+///
+/// The node type, crashing behaviour, packet handling and commands stay specific to each component.
+pub struct NodeCore {
+    /// The id of the node.
+    pub id: NodeId,
+    /// The floods already seen by the node, bounded to limit memory usage.
+    pub seen_flood_ids: SeenFloodCache,
+    /// The channels towards the neighbours of the node.
+    pub packet_send: HashMap<NodeId, Sender<Packet>>,
+    /// The channel the node receives packets on.
+    pub packet_recv: Receiver<Packet>,
+    /// The random generator of the node.
+    pub rng: StdRng,
+    /// The channel towards the simulation controller.
+    pub sim_contr_send: Sender<DroneEvent>,
+    /// The traffic counters of the node.
+    pub metrics: NodeMetrics,
+    /// The last flood id generated by the node, see `NetworkNode::next_flood_id`.
+    pub last_flood_id: u64,
+    /// The neighbours the node refuses to send packets to, see `NetworkNode::set_broadcast_blocklist`.
+    pub broadcast_blocklist: HashSet<NodeId>,
+}
+
+impl NodeCore {
+    /// Creates the state of a node without neighbours.
+    ///
+    /// The random generator is seeded from the operating system; tests wanting a reproducible
+    /// behaviour can replace `rng` with a seeded one.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the node.
+    /// * `packet_recv` - The channel the node receives packets on.
+    /// * `sim_contr_send` - The channel towards the simulation controller.
+    pub fn new(id: NodeId, packet_recv: Receiver<Packet>, sim_contr_send: Sender<DroneEvent>) -> Self {
+        Self {
+            id,
+            seen_flood_ids: SeenFloodCache::new(),
+            packet_send: HashMap::new(),
+            packet_recv,
+            rng: StdRng::from_entropy(),
+            sim_contr_send,
+            metrics: NodeMetrics::default(),
+            last_flood_id: 0,
+            broadcast_blocklist: HashSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network_node::{fragment_message, Command, NetworkNode};
+    use crate::seen_flood_cache::SeenFloodIds;
+    use crossbeam_channel::unbounded;
+    use rand::RngCore;
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{FloodResponse, NodeType, PacketType};
+
+    /// A drone delegating every getter to its `NodeCore`.
+    struct CoreDrone {
+        core: NodeCore,
+    }
+
+    impl NetworkNode for CoreDrone {
+        fn get_id(&self) -> NodeId {
+            self.core.id
+        }
+
+        fn get_node_type(&self) -> NodeType {
+            NodeType::Drone
+        }
+
+        fn get_seen_flood_ids(&mut self) -> &mut dyn SeenFloodIds {
+            &mut self.core.seen_flood_ids
+        }
+
+        fn get_packet_send(&mut self) -> &mut HashMap<NodeId, Sender<Packet>> {
+            &mut self.core.packet_send
+        }
+
+        fn packet_send_ref(&self) -> &HashMap<NodeId, Sender<Packet>> {
+            &self.core.packet_send
+        }
+
+        fn get_packet_receiver(&self) -> &Receiver<Packet> {
+            &self.core.packet_recv
+        }
+
+        fn get_random_generator(&mut self) -> &mut dyn RngCore {
+            &mut self.core.rng
+        }
+
+        fn get_sim_contr_send(&self) -> &Sender<DroneEvent> {
+            &self.core.sim_contr_send
+        }
+
+        fn get_metrics(&mut self) -> &mut NodeMetrics {
+            &mut self.core.metrics
+        }
+
+        fn get_last_flood_id(&mut self) -> &mut u64 {
+            &mut self.core.last_flood_id
+        }
+
+        fn handle_routed_packet(&mut self, _packet: Packet) -> bool {
+            unimplemented!()
+        }
+
+        fn handle_command(&mut self, _command: Command) {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_forward_packet_through_core() {
+        let (sim_contr_send, sim_contr_recv) = unbounded();
+        let mut drone = CoreDrone {
+            core: NodeCore::new(2, unbounded().1, sim_contr_send),
+        };
+        let (next_send, next_recv) = unbounded();
+        drone.add_channel(3, next_send);
+
+        let mut packet = fragment_message(&[1, 2, 3], 5).remove(0);
+        packet.routing_header = SourceRoutingHeader {
+            hop_index: 1,
+            hops: vec![1, 2, 3],
+        };
+        assert!(drone.forward_packet(packet).is_ok());
+
+        assert_eq!(next_recv.try_recv().expect("Expected the packet").session_id, 5);
+        assert!(matches!(sim_contr_recv.try_recv(), Ok(DroneEvent::PacketSent(_))));
+        assert_eq!(drone.core.metrics.forwarded, 1);
+    }
+
+    /// Builds a flood response packet for flood 101 travelling along `hops`.
+    fn flood_response_packet(hop_index: usize, hops: Vec<NodeId>) -> Packet {
+        Packet {
+            pack_type: PacketType::FloodResponse(FloodResponse {
+                flood_id: 101,
+                path_trace: vec![(1, NodeType::Client), (2, NodeType::Drone), (3, NodeType::Server)],
+            }),
+            routing_header: SourceRoutingHeader { hop_index, hops },
+            session_id: 101,
+        }
+    }
+
+    /// Tests that a flood response reaches the default `handle_flood_response` instead of
+    /// `handle_routed_packet`, which relays it along its route.
+    #[test]
+    fn test_flood_response_hook() {
+        let (sim_contr_send, _sim_contr_recv) = unbounded();
+        let mut drone = CoreDrone {
+            core: NodeCore::new(2, unbounded().1, sim_contr_send),
+        };
+        let (next_send, next_recv) = unbounded();
+        drone.add_channel(1, next_send);
+
+        assert!(drone.handle_packet(flood_response_packet(1, vec![3, 2, 1])));
+
+        let relayed = next_recv.try_recv().expect("Expected the flood response");
+        assert!(matches!(
+            relayed.pack_type,
+            PacketType::FloodResponse(FloodResponse { flood_id: 101, .. })
+        ));
+        assert_eq!(relayed.routing_header.hop_index, 2);
+    }
+
+    /// Tests that the default `handle_flood_response` keeps a flood response addressed to
+    /// this node and drops a misrouted one, relaying neither.
+    #[test]
+    fn test_flood_response_hook_final_hop_and_misrouted() {
+        let (sim_contr_send, _sim_contr_recv) = unbounded();
+        let mut drone = CoreDrone {
+            core: NodeCore::new(2, unbounded().1, sim_contr_send),
+        };
+        let (next_send, next_recv) = unbounded();
+        drone.add_channel(1, next_send);
+
+        // This node is the final hop: the response is consumed
+        assert!(drone.handle_packet(flood_response_packet(2, vec![3, 1, 2])));
+        assert!(next_recv.try_recv().is_err());
+
+        // The current hop is another node: the response is dropped
+        assert!(!drone.handle_packet(flood_response_packet(1, vec![3, 4, 1])));
+        assert!(next_recv.try_recv().is_err());
+        assert_eq!(drone.core.metrics.forwarded, 0);
+    }
+}
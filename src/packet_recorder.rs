@@ -0,0 +1,95 @@
+//! Recording of the packets received by a node, and their replay to reproduce routing bugs.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wg_2024::packet::Packet;
+
+use crate::network_node::NetworkNode;
+
+/// A packet received by a node, as stored in a recording.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedPacket {
+    /// When the packet was received, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// The received packet.
+    pub packet: Packet,
+}
+
+/// Writes the packets received by a node to a recording, one JSON object per line.
+///
+/// A node records its packets by returning its recorder from `NetworkNode::get_packet_recorder`.
+/// Each packet is written as soon as it is recorded, so the recording is complete even if the
+/// node panics afterwards.
+pub struct PacketRecorder {
+    file: BufWriter<File>,
+}
+
+impl PacketRecorder {
+    /// Creates a recorder writing to `path`, truncating the file if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the recording.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Appends a received packet to the recording, timestamped with the current time.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The received packet.
+    pub fn record(&mut self, packet: &Packet) -> io::Result<()> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let recorded = RecordedPacket {
+            timestamp_ms,
+            packet: packet.clone(),
+        };
+        serde_json::to_writer(&mut self.file, &recorded)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+/// Reads the packets of a recording, in the order they were received.
+///
+/// # Arguments
+///
+/// * `path` - The path of the recording.
+pub fn read_recording<P: AsRef<Path>>(path: P) -> io::Result<Vec<RecordedPacket>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+        .map(|line| -> io::Result<RecordedPacket> { Ok(serde_json::from_str(&line?)?) })
+        .collect()
+}
+
+/// Feeds the packets of a recording to a node through `handle_packet`, in order.
+///
+/// The packets are handed over without waiting, whatever their timestamps. To reproduce the
+/// behaviour of the recorded node, the replaying node must have the same neighbours and state,
+/// and a random generator seeded like the recorded one.
+///
+/// # Arguments
+///
+/// * `node` - The node the packets are replayed on.
+/// * `path` - The path of the recording.
+///
+/// # Returns
+///
+/// The number of replayed packets.
+pub fn replay<N: NetworkNode + ?Sized, P: AsRef<Path>>(node: &mut N, path: P) -> io::Result<usize> {
+    let recording = read_recording(path)?;
+    let count = recording.len();
+    for recorded in recording {
+        node.handle_packet(recorded.packet);
+    }
+    Ok(count)
+}
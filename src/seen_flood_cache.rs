@@ -0,0 +1,246 @@
+//! Bounded storage for the flood requests and fragments a node has already seen, and for the
+//! number of packets it has forwarded per session.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use wg_2024::network::NodeId;
+
+/// Identifies a flood request by its `(initiator_id, flood_id)` pair.
+pub type FloodKey = (NodeId, u64);
+
+/// Identifies a message fragment by its `(session_id, fragment_index)` pair.
+pub type FragmentKey = (u64, u64);
+
+/// A set of already seen flood requests, as used by `NetworkNode::handle_flood_request`.
+///
+/// It is implemented for `HashSet<FloodKey>`, which keeps every flood request forever,
+/// and for `SeenFloodCache`, which only remembers the most recent ones.
+pub trait SeenFloodIds {
+    /// Returns whether the flood request identified by `key` has been seen.
+    fn contains(&self, key: &FloodKey) -> bool;
+
+    /// Records the flood request identified by `key` as seen.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the flood request was not already recorded.
+    fn insert(&mut self, key: FloodKey) -> bool;
+
+    /// Returns the number of recorded flood requests.
+    fn len(&self) -> usize;
+
+    /// Returns whether no flood request is recorded.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SeenFloodIds for HashSet<FloodKey> {
+    fn contains(&self, key: &FloodKey) -> bool {
+        HashSet::contains(self, key)
+    }
+
+    fn insert(&mut self, key: FloodKey) -> bool {
+        HashSet::insert(self, key)
+    }
+
+    fn len(&self) -> usize {
+        HashSet::len(self)
+    }
+}
+
+/// A fixed-capacity set.
+///
+/// Once the capacity is reached, inserting a new key evicts the oldest one,
+/// so the memory used by a node stays bounded during long simulations.
+/// Membership is only known for the most recent `capacity` keys.
+#[derive(Clone, Debug)]
+pub struct BoundedSet<K> {
+    capacity: usize,
+    keys: HashSet<K>,
+    order: VecDeque<K>,
+}
+
+/// A fixed-capacity set of seen flood requests.
+pub type SeenFloodCache = BoundedSet<FloodKey>;
+
+/// A fixed-capacity set of seen message fragments.
+pub type SeenFragmentCache = BoundedSet<FragmentKey>;
+
+impl<K: Copy + Eq + Hash> BoundedSet<K> {
+    /// Capacity used by `BoundedSet::new`.
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    /// Creates a set remembering up to `DEFAULT_CAPACITY` keys.
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Creates a set remembering up to `capacity` keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "BoundedSet capacity must be positive");
+        Self {
+            capacity,
+            keys: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the maximum number of keys remembered.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns whether `key` is in the set.
+    pub fn contains(&self, key: &K) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// Inserts `key`, evicting the oldest key if the set is full.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key was not already in the set.
+    pub fn insert(&mut self, key: K) -> bool {
+        if !self.keys.insert(key) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        self.order.push_back(key);
+        true
+    }
+
+    /// Returns the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl<K: Copy + Eq + Hash> Default for BoundedSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeenFloodIds for SeenFloodCache {
+    fn contains(&self, key: &FloodKey) -> bool {
+        BoundedSet::contains(self, key)
+    }
+
+    fn insert(&mut self, key: FloodKey) -> bool {
+        BoundedSet::insert(self, key)
+    }
+
+    fn len(&self) -> usize {
+        BoundedSet::len(self)
+    }
+}
+
+/// The number of packets a node has forwarded per session, as used by
+/// `NetworkNode::exceeds_max_forwards`.
+///
+/// Like `BoundedSet`, only the most recent `capacity` sessions are remembered: the count of the
+/// oldest session is evicted when a new one is counted.
+#[derive(Clone, Debug)]
+pub struct ForwardCounts {
+    capacity: usize,
+    counts: HashMap<u64, u32>,
+    order: VecDeque<u64>,
+}
+
+impl ForwardCounts {
+    /// Capacity used by `ForwardCounts::new`.
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    /// Creates a map remembering up to `DEFAULT_CAPACITY` sessions.
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Creates a map remembering up to `capacity` sessions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "ForwardCounts capacity must be positive");
+        Self {
+            capacity,
+            counts: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of packets of a session forwarded so far.
+    pub fn get(&self, session_id: u64) -> u32 {
+        self.counts.get(&session_id).copied().unwrap_or(0)
+    }
+
+    /// Counts one more forwarded packet for a session, evicting the oldest session if full.
+    ///
+    /// # Returns
+    ///
+    /// The number of packets of the session forwarded so far, including this one.
+    pub fn increment(&mut self, session_id: u64) -> u32 {
+        if let Some(count) = self.counts.get_mut(&session_id) {
+            *count = count.saturating_add(1);
+            return *count;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.counts.remove(&oldest);
+            }
+        }
+        self.order.push_back(session_id);
+        self.counts.insert(session_id, 1);
+        1
+    }
+
+    /// Returns the number of sessions remembered.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns whether no session is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+impl Default for ForwardCounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_oldest() {
+        let mut cache = SeenFloodCache::with_capacity(2);
+        assert!(cache.insert((1, 1)));
+        assert!(cache.insert((1, 2)));
+        assert!(!cache.insert((1, 2)));
+        assert!(cache.insert((2, 1)));
+
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&(1, 1)));
+        assert!(cache.contains(&(1, 2)));
+        assert!(cache.contains(&(2, 1)));
+    }
+}
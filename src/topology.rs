@@ -0,0 +1,268 @@
+//! Network topology discovered through flood responses, and routes known to work.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use wg_2024::{network::NodeId, packet::FloodResponse, packet::NodeType};
+
+/// Undirected graph of the network, built from the path traces of flood responses.
+#[derive(Clone, Debug, Default)]
+pub struct TopologyGraph {
+    node_types: HashMap<NodeId, NodeType>,
+    edges: HashMap<NodeId, HashSet<NodeId>>,
+}
+
+impl TopologyGraph {
+    /// Creates an empty topology.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the nodes and links found in the path trace of a flood response.
+    ///
+    /// Every node of the trace is recorded with its type, and each pair of consecutive
+    /// nodes in the trace is recorded as a bidirectional link.
+    ///
+    /// # Arguments
+    ///
+    /// * `resp` - The received flood response.
+    pub fn add_flood_response(&mut self, resp: &FloodResponse) {
+        for (node_id, node_type) in &resp.path_trace {
+            self.node_types.insert(*node_id, *node_type);
+            self.edges.entry(*node_id).or_default();
+        }
+        for pair in resp.path_trace.windows(2) {
+            let (from, to) = (pair[0].0, pair[1].0);
+            if from == to {
+                continue;
+            }
+            self.edges.entry(from).or_default().insert(to);
+            self.edges.entry(to).or_default().insert(from);
+        }
+    }
+
+    /// Returns the known neighbours of a node, sorted by id.
+    /// The vector is empty if the node is unknown.
+    pub fn neighbors(&self, node: NodeId) -> Vec<NodeId> {
+        let mut neighbors: Vec<NodeId> = self
+            .edges
+            .get(&node)
+            .map(|neighbors| neighbors.iter().copied().collect())
+            .unwrap_or_default();
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    /// Returns every known node, sorted by id.
+    pub fn nodes(&self) -> Vec<NodeId> {
+        let mut nodes: Vec<NodeId> = self.node_types.keys().copied().collect();
+        nodes.sort_unstable();
+        nodes
+    }
+
+    /// Returns the type of a node, or `None` if the node is unknown.
+    pub fn node_type(&self, node: NodeId) -> Option<NodeType> {
+        self.node_types.get(&node).copied()
+    }
+
+    /// Computes a shortest route between two nodes, counting every link as one hop.
+    ///
+    /// Only drones may be intermediate hops: clients and servers can only be the
+    /// endpoints of the route, per the protocol.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The first node of the route, usually the node computing it.
+    /// * `to` - The destination of the route.
+    ///
+    /// # Returns
+    ///
+    /// The hops from `from` to `to` (both included), ready to be used in a `SourceRoutingHeader`,
+    /// or `None` if the destination cannot be reached.
+    pub fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Vec<NodeId>> {
+        if !self.edges.contains_key(&from) || !self.edges.contains_key(&to) {
+            return None;
+        }
+        if from == to {
+            return Some(vec![from]);
+        }
+
+        let mut predecessors: HashMap<NodeId, NodeId> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(current) = queue.pop_front() {
+            for neighbor in self.neighbors(current) {
+                if neighbor == from || predecessors.contains_key(&neighbor) {
+                    continue;
+                }
+                predecessors.insert(neighbor, current);
+                if neighbor == to {
+                    let mut path = vec![to];
+                    let mut node = to;
+                    while let Some(&previous) = predecessors.get(&node) {
+                        path.push(previous);
+                        node = previous;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                if self.node_type(neighbor) == Some(NodeType::Drone) {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Routes known to work, by destination, e.g. the routes followed back by the Acks and flood
+/// responses received by a client.
+///
+/// Reusing them avoids flooding the network before every message. A route is dropped as soon
+/// as one of its nodes is reported failing, see `invalidate`.
+#[derive(Clone, Debug, Default)]
+pub struct RouteCache {
+    routes: HashMap<NodeId, Vec<NodeId>>,
+}
+
+impl RouteCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caches a route, replacing any route cached towards the same destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - The destination of the route.
+    /// * `route` - The hops from the node owning the cache to `dest` (both included).
+    pub fn insert(&mut self, dest: NodeId, route: Vec<NodeId>) {
+        self.routes.insert(dest, route);
+    }
+
+    /// Returns the route cached towards a destination, if any.
+    pub fn get(&self, dest: NodeId) -> Option<&[NodeId]> {
+        self.routes.get(&dest).map(Vec::as_slice)
+    }
+
+    /// Drops every route going through a failed node, including the route towards it.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The failed node.
+    ///
+    /// # Returns
+    ///
+    /// The number of dropped routes.
+    pub fn invalidate(&mut self, node: NodeId) -> usize {
+        let count = self.routes.len();
+        // The first hop is the node owning the cache, which is not failing
+        self.routes.retain(|_, route| !route.iter().skip(1).any(|&hop| hop == node));
+        count - self.routes.len()
+    }
+
+    /// Returns the number of cached routes.
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Returns whether no route is cached.
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_flood_response() {
+        let mut topology = TopologyGraph::new();
+        topology.add_flood_response(&FloodResponse {
+            flood_id: 1,
+            path_trace: vec![(1, NodeType::Client), (2, NodeType::Drone), (3, NodeType::Server)],
+        });
+        topology.add_flood_response(&FloodResponse {
+            flood_id: 1,
+            path_trace: vec![(1, NodeType::Client), (2, NodeType::Drone), (4, NodeType::Drone)],
+        });
+
+        assert_eq!(topology.nodes(), vec![1, 2, 3, 4]);
+        assert_eq!(topology.neighbors(2), vec![1, 3, 4]);
+        assert_eq!(topology.neighbors(3), vec![2]);
+        assert!(topology.neighbors(5).is_empty());
+        assert_eq!(topology.node_type(3), Some(NodeType::Server));
+        assert_eq!(topology.node_type(5), None);
+    }
+
+    /// Builds a topology from a list of links between typed nodes.
+    fn build_topology(links: &[((NodeId, NodeType), (NodeId, NodeType))]) -> TopologyGraph {
+        let mut topology = TopologyGraph::new();
+        for (from, to) in links {
+            topology.add_flood_response(&FloodResponse {
+                flood_id: 0,
+                path_trace: vec![*from, *to],
+            });
+        }
+        topology
+    }
+
+    #[test]
+    fn test_shortest_path_diamond() {
+        // 1 - 2 - 5 and 1 - 3 - 4 - 5
+        let topology = build_topology(&[
+            ((1, NodeType::Client), (2, NodeType::Drone)),
+            ((2, NodeType::Drone), (5, NodeType::Server)),
+            ((1, NodeType::Client), (3, NodeType::Drone)),
+            ((3, NodeType::Drone), (4, NodeType::Drone)),
+            ((4, NodeType::Drone), (5, NodeType::Server)),
+        ]);
+
+        assert_eq!(topology.shortest_path(1, 5), Some(vec![1, 2, 5]));
+        assert_eq!(topology.shortest_path(5, 1), Some(vec![5, 2, 1]));
+        assert_eq!(topology.shortest_path(1, 1), Some(vec![1]));
+        assert_eq!(topology.shortest_path(1, 9), None);
+    }
+
+    #[test]
+    fn test_shortest_path_avoids_clients() {
+        // 1 - 6 - 5 is shorter, but 6 is a client: 1 - 2 - 3 - 5 must be used
+        let topology = build_topology(&[
+            ((1, NodeType::Client), (6, NodeType::Client)),
+            ((6, NodeType::Client), (5, NodeType::Server)),
+            ((1, NodeType::Client), (2, NodeType::Drone)),
+            ((2, NodeType::Drone), (3, NodeType::Drone)),
+            ((3, NodeType::Drone), (5, NodeType::Server)),
+        ]);
+
+        assert_eq!(topology.shortest_path(1, 5), Some(vec![1, 2, 3, 5]));
+        // Reaching the client itself is allowed
+        assert_eq!(topology.shortest_path(1, 6), Some(vec![1, 6]));
+    }
+
+    #[test]
+    fn test_shortest_path_no_valid_route() {
+        let topology = build_topology(&[
+            ((1, NodeType::Client), (6, NodeType::Server)),
+            ((6, NodeType::Server), (5, NodeType::Server)),
+        ]);
+
+        assert_eq!(topology.shortest_path(1, 5), None);
+    }
+
+    #[test]
+    fn test_route_cache_invalidate() {
+        let mut cache = RouteCache::new();
+        cache.insert(5, vec![1, 2, 3, 5]);
+        cache.insert(6, vec![1, 4, 6]);
+        cache.insert(7, vec![1, 2, 7]);
+        cache.insert(3, vec![1, 2, 3]);
+
+        assert_eq!(cache.invalidate(3), 2);
+        assert_eq!(cache.get(5), None);
+        assert_eq!(cache.get(3), None);
+        assert_eq!(cache.get(6), Some(&[1, 4, 6][..]));
+        assert_eq!(cache.get(7), Some(&[1, 2, 7][..]));
+        assert_eq!(cache.invalidate(8), 0);
+        assert_eq!(cache.len(), 2);
+    }
+}